@@ -1,3 +1,4 @@
+use aoc_2023::day12::Strategy;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 fn mybench(c: &mut Criterion) {
@@ -5,6 +6,26 @@ fn mybench(c: &mut Criterion) {
     c.bench_function("day12/part2", |b| {
         b.iter(|| black_box(aoc_2023::day12::part2(&input)))
     });
+
+    let mut group = c.benchmark_group("day12/strategies/part1");
+    for strategy in Strategy::ALL {
+        group.bench_function(format!("{strategy:?}"), |b| {
+            b.iter(|| black_box(aoc_2023::day12::part1_with(&input, strategy)))
+        });
+    }
+    group.finish();
+
+    // The naive recursion never finishes on the unfolded records.
+    let mut group = c.benchmark_group("day12/strategies/part2");
+    for strategy in Strategy::ALL
+        .into_iter()
+        .filter(|&s| s != Strategy::Recursive)
+    {
+        group.bench_function(format!("{strategy:?}"), |b| {
+            b.iter(|| black_box(aoc_2023::day12::part2_with(&input, strategy)))
+        });
+    }
+    group.finish();
 }
 
 criterion_group! {
@@ -0,0 +1,20 @@
+use aoc_2023::day14::Representation;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn mybench(c: &mut Criterion) {
+    let input = std::fs::read_to_string("data/day14.input").unwrap();
+    let mut group = c.benchmark_group("day14/part2");
+    for repr in [Representation::Grid, Representation::Bits] {
+        group.bench_function(format!("{repr:?}"), |b| {
+            b.iter(|| black_box(aoc_2023::day14::part2_with(&input, repr)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default();
+    targets = mybench,
+}
+criterion_main!(benches);
@@ -1,3 +1,4 @@
+use aoc_2023::day16::Strategy;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 
 fn mybench(c: &mut Criterion) {
@@ -5,6 +6,14 @@ fn mybench(c: &mut Criterion) {
     c.bench_function("day16/part2", |b| {
         b.iter(|| black_box(aoc_2023::day16::part2(&input)))
     });
+
+    let mut group = c.benchmark_group("day16/strategies/part2");
+    for strategy in Strategy::ALL {
+        group.bench_function(format!("{strategy:?}"), |b| {
+            b.iter(|| black_box(aoc_2023::day16::part2_with(&input, strategy, 1)))
+        });
+    }
+    group.finish();
 }
 
 criterion_group! {
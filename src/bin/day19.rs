@@ -0,0 +1,30 @@
+//! Prints how each part in a day19 input is sorted, e.g.
+//!
+//! ```text
+//! $ cargo run --bin day19 -- data/day19.input
+//! {x=787,m=2655,a=1222,s=2876}: in -> qqz -> qs -> lnx -> A
+//! ```
+//!
+//! Pass `--rules` to also print every rule that each part was checked against.
+use anyhow::Context;
+
+fn main() -> anyhow::Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let rules = args.iter().any(|a| a == "--rules");
+    args.retain(|a| a != "--rules");
+    let [path] = args.as_slice() else {
+        anyhow::bail!("usage: day19 [--rules] <input>");
+    };
+    let input = std::fs::read_to_string(path).with_context(|| format!("could not read {path}"))?;
+    for (item, trace) in aoc_2023::day19::explain(&input)? {
+        println!("{item}: {trace}");
+        if rules {
+            for step in &trace.steps {
+                let condition = step.condition.as_deref().unwrap_or("(fallback)");
+                let outcome = if step.matched { "matched" } else { "no match" };
+                println!("  {}[{}] {condition}: {outcome}", step.workflow, step.rule);
+            }
+        }
+    }
+    Ok(())
+}
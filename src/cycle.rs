@@ -0,0 +1,149 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    hash::Hash,
+};
+
+/// Describes an eventually-periodic sequence x_0, x_1 = f(x_0), x_2 = f(x_1), ...
+/// The first `mu` states are a lead-in, after which the states repeat every `lambda` steps.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Cycle {
+    pub mu: usize,
+    pub lambda: usize,
+}
+impl Cycle {
+    /// The smallest step whose state is guaranteed to be identical to the state at `target`.
+    pub fn reduce(&self, target: usize) -> usize {
+        if target < self.mu {
+            target
+        } else {
+            self.mu + (target - self.mu) % self.lambda
+        }
+    }
+}
+
+/// Brent's algorithm. Only ever holds two copies of the state, so it's a good fit
+/// when states are cheap to compare but expensive to store.
+pub fn brent<T: Clone + Eq>(init: &T, mut step: impl FnMut(&mut T)) -> Cycle {
+    let mut power = 1;
+    let mut lambda = 1;
+    let mut tortoise = init.clone();
+    let mut hare = init.clone();
+    step(&mut hare);
+    while tortoise != hare {
+        if power == lambda {
+            tortoise = hare.clone();
+            power *= 2;
+            lambda = 0;
+        }
+        step(&mut hare);
+        lambda += 1;
+    }
+
+    let mut tortoise = init.clone();
+    let mut hare = init.clone();
+    for _ in 0..lambda {
+        step(&mut hare);
+    }
+    let mut mu = 0;
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        mu += 1;
+    }
+    Cycle { mu, lambda }
+}
+
+/// Detects a cycle by remembering a `key` for every state we've seen. The key should
+/// uniquely identify the state, but it can be much more compact than the state itself.
+///
+/// On return, `state` has been advanced to step `mu + lambda`.
+pub fn hashed<T, K: Hash + Eq>(
+    state: &mut T,
+    step: impl FnMut(&mut T),
+    key: impl FnMut(&T) -> K,
+) -> Cycle {
+    match advance(state, step, key, usize::MAX) {
+        (_, Some(cycle)) => cycle,
+        (idx, None) => panic!("no cycle found after {idx} steps"),
+    }
+}
+
+/// Computes the state at step `target`, skipping over as many full cycles as possible.
+pub fn state_at<T, K: Hash + Eq>(
+    mut state: T,
+    mut step: impl FnMut(&mut T),
+    key: impl FnMut(&T) -> K,
+    target: usize,
+) -> T {
+    let (idx, cycle) = advance(&mut state, &mut step, key, target);
+    let remaining = match cycle {
+        Some(cycle) => cycle.reduce(target) - cycle.reduce(idx),
+        None => target - idx,
+    };
+    for _ in 0..remaining {
+        step(&mut state);
+    }
+    state
+}
+
+// Steps `state` forward until we either find a cycle or reach `limit`, returning the step we stopped at.
+fn advance<T, K: Hash + Eq>(
+    state: &mut T,
+    mut step: impl FnMut(&mut T),
+    mut key: impl FnMut(&T) -> K,
+    limit: usize,
+) -> (usize, Option<Cycle>) {
+    let mut record: HashMap<K, usize> = HashMap::new();
+    let mut idx = 0;
+    while idx < limit {
+        match record.entry(key(state)) {
+            Entry::Occupied(prev) => {
+                let mu = *prev.get();
+                return (
+                    idx,
+                    Some(Cycle {
+                        mu,
+                        lambda: idx - mu,
+                    }),
+                );
+            }
+            Entry::Vacant(slot) => slot.insert(idx),
+        };
+        step(state);
+        idx += 1;
+    }
+    (idx, None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 0, 1, 2, 3, 4, 5, 6, 7, 3, 4, 5, 6, 7, 3, ...
+    fn step(x: &mut u32) {
+        *x = if *x == 7 { 3 } else { *x + 1 };
+    }
+
+    #[test]
+    fn brent_smoke_test() {
+        assert_eq!(brent(&0, step), Cycle { mu: 3, lambda: 5 });
+        assert_eq!(brent(&5, step), Cycle { mu: 0, lambda: 5 });
+    }
+
+    #[test]
+    fn hashed_smoke_test() {
+        let mut state = 0;
+        assert_eq!(hashed(&mut state, step, |&x| x), Cycle { mu: 3, lambda: 5 });
+        assert_eq!(state, 3);
+    }
+
+    #[test]
+    fn state_at_matches_direct_simulation() {
+        let mut direct = 0;
+        for target in 0..50 {
+            assert_eq!(state_at(0, step, |&x| x, target), direct);
+            step(&mut direct);
+        }
+        assert_eq!(state_at(0, step, |&x| x, 1_000_000_000), 5);
+    }
+}
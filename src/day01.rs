@@ -75,7 +75,7 @@ pub fn part1(input: &str) -> anyhow::Result<u32> {
         let Some(first) = digits.next() else {
             bail!("no digits in line {}", line);
         };
-        let last = digits.last().unwrap_or(first);
+        let last = digits.next_back().unwrap_or(first);
         total += 10 * first + last;
     }
     Ok(total)
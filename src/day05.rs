@@ -170,17 +170,17 @@ of the almanac. What is the lowest location number that corresponds to any of
 the initial seed numbers?
   */
 
-use std::{cmp, ops::Range};
-
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
 use nom::{
     bytes::complete::tag,
-    character::complete::{digit1, multispace1, not_line_ending},
+    character::complete::{digit1, i64, multispace1, not_line_ending, space1},
     combinator::{all_consuming, map_res},
     multi::separated_list1,
     IResult,
 };
 
+use crate::interval::{IntervalSet, Piece, RangeMap};
+
 pub fn part1(input: &str) -> anyhow::Result<i64> {
     let almanac = parse_almanac(input)?;
     let locations: Vec<i64> = almanac
@@ -197,125 +197,230 @@ pub fn part1(input: &str) -> anyhow::Result<i64> {
 
 pub fn part2(input: &str) -> anyhow::Result<i64> {
     let almanac = parse_almanac(input)?;
-    let mut locations = Vec::new();
-    for chunk in almanac.seeds.chunks(2) {
-        let r = chunk[0]..chunk[0] + chunk[1];
-        let locs = almanac.translate_range(r.clone());
-        locations.extend(locs);
-    }
-    locations
-        .into_iter()
-        .map(|r| r.start)
-        .min()
+    almanac
+        .min_location(&almanac.seed_ranges()?)?
+        .map(|(_seed, location)| location)
         .ok_or(anyhow!("empty locations"))
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-struct Almanac {
-    seeds: Vec<i64>,
+pub struct Almanac {
+    pub seeds: Vec<i64>,
+    // Private, so that every map has been through `validate_map`.
     maps: Vec<Map>,
 }
 
 impl Almanac {
-    fn translate(&self, mut input: i64) -> i64 {
+    pub fn maps(&self) -> &[Map] {
+        &self.maps
+    }
+
+    pub fn translate(&self, mut input: i64) -> i64 {
         for m in &self.maps {
             input = m.translate(input);
         }
         input
     }
 
-    fn translate_range(&self, input: Range<i64>) -> Vec<Range<i64>> {
-        let mut ranges = vec![input];
+    /// Interprets `seeds` as (start, length) pairs, the way part 2 does.
+    pub fn seed_ranges(&self) -> anyhow::Result<IntervalSet> {
+        if !self.seeds.len().is_multiple_of(2) {
+            bail!(
+                "seeds should come in (start, length) pairs, but there are {}",
+                self.seeds.len()
+            );
+        }
+        self.seeds
+            .chunks(2)
+            .map(|chunk| match chunk[0].checked_add(chunk[1]) {
+                Some(end) => Ok(chunk[0]..end),
+                None => bail!("seed range {} {} overflows i64", chunk[0], chunk[1]),
+            })
+            .collect()
+    }
+
+    /// Collapses the whole seed-to-location chain into a single map.
+    pub fn compose(&self) -> anyhow::Result<RangeMap> {
+        self.maps
+            .iter()
+            .try_fold(RangeMap::identity(), |acc, m| Ok(acc.then(&m.range_map()?)))
+    }
+
+    /// Every seed that ends up somewhere in `locations`.
+    pub fn untranslate(&self, locations: &IntervalSet) -> anyhow::Result<IntervalSet> {
+        self.maps
+            .iter()
+            .rev()
+            .try_fold(locations.clone(), |acc, m| m.untranslate(&acc))
+    }
+
+    /// The lowest location reachable from `seeds`, along with the lowest seed that reaches it.
+    pub fn min_location(&self, seeds: &IntervalSet) -> anyhow::Result<Option<(i64, i64)>> {
+        let Some(location) = self.compose()?.apply_set(seeds).min() else {
+            return Ok(None);
+        };
+        let seed = self
+            .untranslate(&IntervalSet::from(location..location + 1))?
+            .intersection(seeds)
+            .min();
+        Ok(seed.map(|seed| (seed, location)))
+    }
+
+    /// Follows a single seed through every map, recording which translation (if any) applied.
+    pub fn trace(&self, mut input: i64) -> Vec<Step> {
+        let mut steps = Vec::new();
         for m in &self.maps {
-            let mut next = Vec::new();
-            for r in ranges {
-                next.extend(m.translate_range(r));
-            }
-            ranges = next;
+            let translation = m.find(input).cloned();
+            let output = m.translate(input);
+            steps.push(Step {
+                map: m.name.clone(),
+                translation,
+                input,
+                output,
+            });
+            input = output;
         }
-        ranges
+        steps
     }
 }
 
+/// One hop of [`Almanac::trace`]. `translation` is `None` when the value passed through unchanged.
 #[derive(Debug, PartialEq, Eq, Clone)]
-struct Map {
-    name: String,
+pub struct Step {
+    pub map: String,
+    pub translation: Option<Translation>,
+    pub input: i64,
+    pub output: i64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Map {
+    pub name: String,
+    // Sorted by `src` and non-overlapping, which `parse_almanac` checks.
     translations: Vec<Translation>,
 }
 
 impl Map {
-    fn translate(&self, input: i64) -> i64 {
-        for t in &self.translations {
-            if t.src <= input && input < t.src + t.len {
-                return t.dst + (input - t.src);
-            }
-        }
-        input
+    pub fn translations(&self) -> &[Translation] {
+        &self.translations
     }
 
-    fn translate_range(&self, mut input: Range<i64>) -> Vec<Range<i64>> {
-        // Example:
-        // input = 0 .. 10
-        // translations = [
-        //   1..4 -> 11..14
-        //   6..9 -> 16..19
-        // ]
-        // should result in
-        // [0..1] + [11..14] + [5..6] + [16..19] + [9..10]
-
-        let mut acc = Vec::new();
-        let mut ts = self.translations.iter().cloned().peekable();
-        while !input.is_empty() {
-            match ts.peek().cloned() {
-                None => {
-                    // No translations left, wrap up and return.
-                    acc.push(input);
-                    break;
-                }
-                Some(t) if input.start < t.src => {
-                    // We have some dangling bits before this translation,
-                    // handle those.
-                    let end = cmp::min(input.end, t.src);
-                    acc.push(input.start..end);
-                    input = end..input.end;
-                }
-                Some(t) if input.start >= t.src + t.len => {
-                    // This translation is irrelevant to us, skip it.
-                    ts.next();
-                }
-                Some(t) => {
-                    // Relevant translation: figure out which section can be
-                    // translated, and consume it.
-                    ts.next();
-                    let lo = cmp::max(input.start, t.src);
-                    let hi = cmp::min(input.end, t.src + t.len);
-                    acc.push(t.dst + lo - t.src..t.dst + hi - t.src);
-                    input = hi..input.end;
-                }
-            }
+    pub fn translate(&self, input: i64) -> i64 {
+        match self.find(input) {
+            Some(t) => t.dst + (input - t.src),
+            None => input,
         }
-        acc
+    }
+
+    /// The translation that applies to `input`, if any.
+    pub fn find(&self, input: i64) -> Option<&Translation> {
+        self.translations
+            .iter()
+            .find(|t| t.src <= input && input < t.src + t.len)
+    }
+
+    /// Every input that this map sends somewhere in `output`.
+    pub fn untranslate(&self, output: &IntervalSet) -> anyhow::Result<IntervalSet> {
+        Ok(self.range_map()?.preimage(output))
+    }
+
+    fn range_map(&self) -> anyhow::Result<RangeMap> {
+        let pieces = self.translations.iter().map(|t| Piece {
+            src: t.src..t.src + t.len,
+            offset: t.dst - t.src,
+        });
+        RangeMap::new(pieces).map_err(|err| anyhow!("{}: {err}", self.name))
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
-struct Translation {
-    src: i64,
-    dst: i64,
-    len: i64,
+pub struct Translation {
+    pub src: i64,
+    pub dst: i64,
+    pub len: i64,
 }
 
-fn parse_almanac(input: &str) -> anyhow::Result<Almanac> {
-    let (_, almanac) = all_consuming(almanac_parser)(input)
+pub fn parse_almanac(input: &str) -> anyhow::Result<Almanac> {
+    let (_, (seeds, raw_maps)) = all_consuming(almanac_parser)(input)
         .map_err(|err| anyhow!("could not parse {input}: {err}"))?;
-    Ok(almanac)
+    let line_of = |rest: &str| input[..input.len() - rest.len()].matches('\n').count() + 1;
+
+    let mut maps: Vec<Map> = Vec::new();
+    let mut prev_to = "seed";
+    for raw in raw_maps {
+        let line = line_of(raw.name);
+        let Some((from, to)) = raw
+            .name
+            .strip_suffix(" map:")
+            .and_then(|name| name.split_once("-to-"))
+        else {
+            bail!(
+                "line {line}: expected a header like `a-to-b map:`, got `{}`",
+                raw.name
+            );
+        };
+        if from != prev_to {
+            bail!("line {line}: {from}-to-{to} should start from {prev_to}");
+        }
+        prev_to = to;
+        maps.push(validate_map(
+            format!("{from}-to-{to}"),
+            raw.translations,
+            line_of,
+        )?);
+    }
+    Ok(Almanac { seeds, maps })
+}
+
+// Sorts translations by `src` (which `Map` relies on), rejecting any that are malformed or ambiguous.
+fn validate_map<'a>(
+    name: String,
+    mut translations: Vec<(&'a str, Translation)>,
+    line_of: impl Fn(&'a str) -> usize,
+) -> anyhow::Result<Map> {
+    if let Some((at, t)) = translations.iter().find(|(_, t)| t.len < 0) {
+        bail!(
+            "line {}: {name} has negative length translation {t:?}",
+            line_of(at)
+        );
+    }
+    for (at, t) in &translations {
+        if t.src.checked_add(t.len).is_none() || t.dst.checked_add(t.len).is_none() {
+            bail!(
+                "line {}: {name} has translation {t:?} running past i64::MAX",
+                line_of(at)
+            );
+        }
+    }
+    translations.sort_by_key(|(_, t)| t.src);
+    for w in translations.windows(2) {
+        let ((_, prev), (at, next)) = (&w[0], &w[1]);
+        // Can't overflow, since we checked every translation's end above.
+        if prev.src + prev.len > next.src {
+            bail!(
+                "line {}: {name} has overlapping translations {prev:?} and {next:?}",
+                line_of(at)
+            );
+        }
+    }
+    Ok(Map {
+        name,
+        translations: translations.into_iter().map(|(_, t)| t).collect(),
+    })
 }
 
-fn almanac_parser(input: &str) -> IResult<&str, Almanac> {
+// A map as it appears in the input, before validation. We hang on to the unparsed input
+// at each item so that we can report line numbers.
+struct RawMap<'a> {
+    name: &'a str,
+    translations: Vec<(&'a str, Translation)>,
+}
+
+fn almanac_parser(input: &str) -> IResult<&str, (Vec<i64>, Vec<RawMap<'_>>)> {
     let (input, seeds) = seeds_parser(input)?;
     let (input, _) = multispace1(input)?;
     let (input, maps) = separated_list1(multispace1, map_parser)(input)?;
-    Ok((input, Almanac { seeds, maps }))
+    Ok((input, (seeds, maps)))
 }
 fn seeds_parser(input: &str) -> IResult<&str, Vec<i64>> {
     let (input, _) = tag("seeds:")(input)?;
@@ -323,26 +428,20 @@ fn seeds_parser(input: &str) -> IResult<&str, Vec<i64>> {
     let (input, seeds) = separated_list1(multispace1, map_res(digit1, str::parse))(input)?;
     Ok((input, seeds))
 }
-fn map_parser(input: &str) -> IResult<&str, Map> {
+fn map_parser(input: &str) -> IResult<&str, RawMap<'_>> {
     let (input, name) = not_line_ending(input)?;
     let (input, _) = multispace1(input)?;
-    let (input, mut translations) = separated_list1(multispace1, translation_parser)(input)?;
-    translations.sort_by_key(|t| t.src);
-    Ok((
-        input,
-        Map {
-            name: name.to_owned(),
-            translations,
-        },
-    ))
+    let (input, translations) = separated_list1(multispace1, translation_parser)(input)?;
+    Ok((input, RawMap { name, translations }))
 }
-fn translation_parser(input: &str) -> IResult<&str, Translation> {
-    let (input, dst) = map_res(digit1, str::parse)(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, src) = map_res(digit1, str::parse)(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, len) = map_res(digit1, str::parse)(input)?;
-    Ok((input, Translation { src, dst, len }))
+fn translation_parser(input: &str) -> IResult<&str, (&str, Translation)> {
+    let at = input;
+    let (input, dst) = i64(input)?;
+    let (input, _) = space1(input)?;
+    let (input, src) = i64(input)?;
+    let (input, _) = space1(input)?;
+    let (input, len) = i64(input)?;
+    Ok((input, (at, Translation { src, dst, len })))
 }
 
 #[cfg(test)]
@@ -392,6 +491,45 @@ mod test {
         assert_eq!(almanac.maps.len(), 7);
     }
 
+    #[test]
+    fn parse_sorts_translations() {
+        let almanac = parse_almanac(SAMPLE_INPUT.trim()).unwrap();
+        let srcs: Vec<i64> = almanac.maps[2].translations.iter().map(|t| t.src).collect();
+        assert_eq!(srcs, vec![0, 7, 11, 53]);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_maps() {
+        let err = |input: &str| parse_almanac(input).unwrap_err().to_string();
+        assert_eq!(
+            err("seeds: 1\n\nseed-to-soil map:\n0 0 5\n10 4 2"),
+            "line 5: seed-to-soil has overlapping translations \
+             Translation { src: 0, dst: 0, len: 5 } and Translation { src: 4, dst: 10, len: 2 }",
+        );
+        assert_eq!(
+            err("seeds: 1\n\nseed-to-soil map:\n0 0 5\n10 20 -2"),
+            "line 5: seed-to-soil has negative length translation Translation { src: 20, dst: 10, len: -2 }",
+        );
+        assert_eq!(
+            err("seeds: 1\n\nseed-to-soil map:\n0 0 5\n0 9223372036854775800 100"),
+            "line 5: seed-to-soil has translation \
+             Translation { src: 9223372036854775800, dst: 0, len: 100 } running past i64::MAX",
+        );
+        assert_eq!(
+            err("seeds: 1\n\nseed-to-soil map:\n9223372036854775800 0 100"),
+            "line 4: seed-to-soil has translation \
+             Translation { src: 0, dst: 9223372036854775800, len: 100 } running past i64::MAX",
+        );
+        assert_eq!(
+            err("seeds: 1\n\nseed-to-soil map:\n0 0 5\n\nwater-to-light map:\n1 1 1"),
+            "line 6: water-to-light should start from soil",
+        );
+        assert_eq!(
+            err("seeds: 1\n\nseed-to-soil:\n0 0 5"),
+            "line 3: expected a header like `a-to-b map:`, got `seed-to-soil:`",
+        );
+    }
+
     #[test]
     fn part1_sample_input() {
         assert_eq!(part1(SAMPLE_INPUT.trim()).unwrap(), 35);
@@ -399,7 +537,7 @@ mod test {
     #[test]
     fn part1_real_input() {
         assert_eq!(
-            part1(&std::fs::read_to_string("data/day05.input").unwrap().trim()).unwrap(),
+            part1(std::fs::read_to_string("data/day05.input").unwrap().trim()).unwrap(),
             322500873,
         );
     }
@@ -422,8 +560,78 @@ mod test {
             ],
         };
         assert_eq!(
-            m.translate_range(0..10),
-            vec![0..1, 11..14, 4..6, 16..19, 9..10],
+            m.range_map()
+                .unwrap()
+                .apply_set(&IntervalSet::from(0..10))
+                .ranges(),
+            &[0..1, 4..6, 9..10, 11..14, 16..19],
+        );
+    }
+
+    #[test]
+    fn compose_matches_translate() {
+        let almanac = parse_almanac(SAMPLE_INPUT.trim()).unwrap();
+        let composed = almanac.compose().unwrap();
+        for seed in 0..200 {
+            assert_eq!(composed.apply(seed), almanac.translate(seed));
+        }
+    }
+
+    #[test]
+    fn untranslate_matches_translate() {
+        let almanac = parse_almanac(SAMPLE_INPUT.trim()).unwrap();
+        let locations = IntervalSet::from(40..60);
+        let seeds = almanac.untranslate(&locations).unwrap();
+        for seed in 0..200 {
+            assert_eq!(
+                seeds.contains(seed),
+                locations.contains(almanac.translate(seed)),
+                "seed = {seed}",
+            );
+        }
+    }
+
+    #[test]
+    fn min_location_sample_input() {
+        let almanac = parse_almanac(SAMPLE_INPUT.trim()).unwrap();
+        // From the puzzle text: seed 82 ends up at location 46.
+        assert_eq!(
+            almanac
+                .min_location(&almanac.seed_ranges().unwrap())
+                .unwrap(),
+            Some((82, 46))
+        );
+        let steps = almanac.trace(82);
+        assert_eq!(steps.len(), 7);
+        assert_eq!(steps[0].map, "seed-to-soil");
+        assert_eq!(
+            steps[0].translation,
+            Some(Translation {
+                src: 50,
+                dst: 52,
+                len: 48
+            })
+        );
+        assert_eq!(steps.last().unwrap().output, 46);
+    }
+
+    #[test]
+    fn bad_seed_ranges_are_errors() {
+        let input = SAMPLE_INPUT
+            .trim()
+            .replacen("seeds: 79 14 55 13", "seeds: 79 14 55", 1);
+        let almanac = parse_almanac(&input).unwrap();
+        assert!(almanac.seed_ranges().is_err());
+        assert!(part2(&input).is_err());
+
+        let input =
+            SAMPLE_INPUT
+                .trim()
+                .replacen("seeds: 79 14 55 13", "seeds: 9223372036854775800 100", 1);
+        let err = parse_almanac(&input).unwrap().seed_ranges().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "seed range 9223372036854775800 100 overflows i64"
         );
     }
 
@@ -434,7 +642,7 @@ mod test {
     #[test]
     fn part2_real_input() {
         assert_eq!(
-            part2(&std::fs::read_to_string("data/day05.input").unwrap().trim()).unwrap(),
+            part2(std::fs::read_to_string("data/day05.input").unwrap().trim()).unwrap(),
             108956227,
         );
     }
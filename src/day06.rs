@@ -128,12 +128,15 @@ use nom::{
     IResult,
 };
 
-pub fn part1(input: &str) -> anyhow::Result<i64> {
+pub fn part1(input: &str) -> anyhow::Result<u128> {
     let races = parse_races(input)?;
-    Ok(races.into_iter().map(ways_to_win).product())
+    races.into_iter().try_fold(1u128, |acc, race| {
+        acc.checked_mul(ways_to_win(race))
+            .ok_or_else(|| anyhow!("product of ways to win overflows u128"))
+    })
 }
 
-pub fn part2(input: &str) -> anyhow::Result<i64> {
+pub fn part2(input: &str) -> anyhow::Result<u128> {
     let races = parse_races(input)?;
     // take all the races and concatenate them into one big race
     let acc = Race {
@@ -151,28 +154,62 @@ pub fn part2(input: &str) -> anyhow::Result<i64> {
     Ok(ways_to_win(acc))
 }
 
-fn ways_to_win(race: Race) -> i64 {
-    // traveled(hold; duration) = hold * (duration - hold)
-    // We care about the min and max values of hold such that
-    //   traveled(hold; race.time) > race.distance
-    // x * (t - x) > d
-    // x * t - x * x - d == 0
-    // x * x - x * t + d == 0
-    // (t +- sqrt(t*t - 4*d)) / 2
-    let (a, b, c) = (1.0, -race.time as f64, race.distance as f64);
-    let sqrt = f64::sqrt(b * b - 4.0 * a * c);
-    if sqrt.is_nan() {
+/// The number of ways to hold the button and travel strictly farther than `race.distance`.
+/// Exact for any race, even where a float square root would round the wrong way.
+pub fn ways_to_win(race: Race) -> u128 {
+    let Race {
+        time: t,
+        distance: d,
+    } = race;
+    // Holding for x travels x * (t - x), which is symmetric around t / 2 and increasing
+    // below it. So we win for every x in lo..=(t - lo), where lo is the first winning hold.
+    if !beats(t / 2, race) {
         return 0;
     }
-    let min = (-b - sqrt) / (2.0 * a);
-    let max = (-b + sqrt) / (2.0 * a);
-    (max.ceil() as i64 - 1) - (min.floor() as i64 + 1) + 1
+    let lo = match t.checked_mul(t) {
+        // lo is just above the smaller root of x * x - t * x + d, (t - sqrt(t * t - 4 * d)) / 2.
+        // We know the discriminant is positive, since some hold wins.
+        Some(tt) => {
+            let estimate = (t - (tt - 4 * d).isqrt()) / 2;
+            // With r the real root, isqrt is within one below the true square root, so
+            // (t - isqrt) / 2 < r + 1/2 and the integer division gives estimate <= floor(r) + 1,
+            // which is lo. It's also at least floor(r), so this steps up at most once.
+            let mut lo = estimate;
+            while !beats(lo, race) {
+                lo += 1;
+            }
+            lo
+        }
+        // Too big to square, so find the boundary by bisection instead.
+        None => {
+            let (mut lo, mut hi) = (0, t / 2);
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if beats(mid, race) {
+                    hi = mid;
+                } else {
+                    lo = mid + 1;
+                }
+            }
+            lo
+        }
+    };
+    t - 2 * lo + 1
 }
 
-#[derive(PartialEq, Eq, Debug)]
-struct Race {
-    time: i64,
-    distance: i64,
+// Whether holding the button for `hold` beats the record.
+fn beats(hold: u128, race: Race) -> bool {
+    match hold.checked_mul(race.time - hold) {
+        Some(traveled) => traveled > race.distance,
+        // Anything that overflows is certainly farther than a record that fits.
+        None => true,
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Race {
+    pub time: u128,
+    pub distance: u128,
 }
 
 fn parse_races(input: &str) -> anyhow::Result<Vec<Race>> {
@@ -192,7 +229,7 @@ fn races_parser(input: &str) -> IResult<&str, Vec<Race>> {
     Ok((input, races))
 }
 
-fn numbers_parser(input: &str) -> IResult<&str, Vec<i64>> {
+fn numbers_parser(input: &str) -> IResult<&str, Vec<u128>> {
     delimited(
         multispace0,
         separated_list1(multispace1, map_res(digit1, str::parse)),
@@ -248,6 +285,86 @@ mod test {
         assert_eq!(part2(SAMPLE_INPUT).unwrap(), 71503);
     }
 
+    // The original implementation, for comparison.
+    fn ways_to_win_float(race: Race) -> u128 {
+        let (a, b, c) = (1.0, -(race.time as f64), race.distance as f64);
+        let sqrt = f64::sqrt(b * b - 4.0 * a * c);
+        if sqrt.is_nan() {
+            return 0;
+        }
+        let min = (-b - sqrt) / (2.0 * a);
+        let max = (-b + sqrt) / (2.0 * a);
+        ((max.ceil() as i64 - 1) - (min.floor() as i64 + 1) + 1).max(0) as u128
+    }
+
+    fn ways_to_win_brute(race: Race) -> u128 {
+        (0..=race.time)
+            .filter(|&hold| hold * (race.time - hold) > race.distance)
+            .count() as u128
+    }
+
+    #[test]
+    fn ways_to_win_matches_brute_force() {
+        for time in 0..60 {
+            for distance in 0..=(time * time / 4 + 1) {
+                let race = Race { time, distance };
+                let expected = ways_to_win_brute(race);
+                assert_eq!(ways_to_win(race), expected, "{race:?}");
+                assert_eq!(ways_to_win_float(race), expected, "{race:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn ways_to_win_excludes_ties() {
+        // The record is exactly reachable by holding for `hold`, so the roots are integers
+        // and matching the record doesn't count.
+        for (time, hold) in [
+            (30, 10),
+            (71530, 13),
+            (1 << 40, 3),
+            (u64::MAX as u128, 1 << 20),
+        ] {
+            let race = Race {
+                time,
+                distance: hold * (time - hold),
+            };
+            assert_eq!(ways_to_win(race), time - 2 * hold - 1, "{race:?}");
+        }
+        // Perfect square discriminant with the record at the very top.
+        let race = Race {
+            time: 1 << 60,
+            distance: 1 << 118,
+        };
+        assert_eq!(ways_to_win(race), 0);
+        let race = Race {
+            time: 1 << 60,
+            distance: (1 << 118) - 1,
+        };
+        assert_eq!(ways_to_win(race), 1);
+    }
+
+    #[test]
+    fn ways_to_win_beyond_f64() {
+        // Past 2^53 the float version can't even represent every hold.
+        let time: u128 = (1 << 60) + 1;
+        let hold: u128 = 123_456_789;
+        let race = Race {
+            time,
+            distance: hold * (time - hold),
+        };
+        assert_eq!(ways_to_win(race), time - 2 * hold - 1);
+        assert_ne!(ways_to_win_float(race), time - 2 * hold - 1);
+
+        // Too long to square in a u128, so this goes through the bisection.
+        let (time, hold) = (u128::MAX / 3, 2);
+        let race = Race {
+            time,
+            distance: hold * (time - hold),
+        };
+        assert_eq!(ways_to_win(race), time - 2 * hold - 1);
+    }
+
     #[test]
     fn part2_real_input() {
         assert_eq!(
@@ -120,172 +120,369 @@ Using the new joker rule, find the rank of every hand in your set. What are the
 
 use std::collections::BTreeMap;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
+use itertools::Itertools;
 use nom::{
-    branch::alt,
-    bytes::complete::tag,
-    character::complete::{digit1, multispace0, multispace1},
-    combinator::{all_consuming, map_res, value},
-    multi::{count, separated_list1},
+    character::complete::{alphanumeric1, digit1, multispace0, multispace1},
+    combinator::{all_consuming, map_res},
+    multi::separated_list1,
     sequence::{delimited, separated_pair},
     IResult,
 };
 
 pub fn part1(input: &str) -> anyhow::Result<u64> {
-    let mut bids = parse_bids(input)?;
-    bids.sort_by_cached_key(|b| (categorize(b.hand), b.hand));
-    let total = bids
-        .into_iter()
-        .enumerate()
-        .map(|(i, bid)| (i + 1) as u64 * bid.amount)
-        .sum::<u64>();
-    Ok(total)
+    winnings(input, &Rules::standard())
 }
 
 pub fn part2(input: &str) -> anyhow::Result<u64> {
+    winnings(input, &Rules::standard().with_wildcards("J"))
+}
+
+/// Ranks every hand under `rules` and adds up each bid multiplied by its rank.
+pub fn winnings(input: &str, rules: &Rules) -> anyhow::Result<u64> {
     let bids = parse_bids(input)?;
-    let mut categorized: Vec<(Kind, Bid)> = bids
-        .into_iter()
-        .map(|bid| {
-            // Translate J into jokers, the lowest value card.
-            let hand: [u8; 5] = bid
-                .hand
-                .into_iter()
-                .map(|card| if card == 11 { 1 } else { card })
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap();
-            let mut t = tally(hand);
-
-            // It is always optimal to coerce the joker into the modal non-joker card.
-            let mut candidates: Vec<u8> = t.keys().filter(|&&k| k != 1).copied().collect();
-            candidates.sort_by_cached_key(|k| t.get(k).copied().unwrap());
-            // It is technically possible that there is no candidate because the hand is entirely jokers
-            if let Some(best) = candidates.last() {
-                if let Some(jokers) = t.remove(&1) {
-                    *t.get_mut(best).unwrap() += jokers;
-                }
-            }
-            (
-                compute_kind(t),
-                Bid {
-                    hand,
-                    amount: bid.amount,
-                },
-            )
-        })
-        .collect();
-    categorized.sort_by_key(|&(kind, bid)| (kind, bid.hand));
-    let total = categorized
+    let mut ranked = bids
+        .iter()
+        .map(|bid| Ok((rules.rank(bid.hand)?, bid.amount)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    ranked.sort();
+    let total = ranked
         .into_iter()
         .enumerate()
-        .map(|(i, (_, bid))| (i + 1) as u64 * bid.amount)
+        .map(|(i, (_, amount))| (i + 1) as u64 * amount)
         .sum::<u64>();
     Ok(total)
 }
 
+/// Everything that distinguishes one game of Camel Cards from another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    /// Card labels, from weakest to strongest. This breaks ties between hands of the same category.
+    pub order: Vec<char>,
+    /// Cards that act like whichever other card would make the hand strongest.
+    pub wildcards: Vec<char>,
+    /// Hand categories, from weakest to strongest. A hand belongs to the strongest one it satisfies.
+    pub categories: Vec<Category>,
+    pub hand_size: usize,
+    pub wildcard_strategy: WildcardStrategy,
+}
+/// How to decide what the wildcards in a hand stand in for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WildcardStrategy {
+    /// Pile every wildcard onto the most common non-wild card. Fast, and optimal for the
+    /// standard categories, but not necessarily for custom ones (see `find_counterexample`).
+    Modal,
+    /// Try every possible assignment and keep the best.
+    Exhaustive,
+}
+/// A kind of hand, described by the groups of matching cards it needs. For example, a full house
+/// is `[3, 2]`: some card at least 3 times, and a different card at least twice.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Category {
+    pub name: String,
+    pub groups: Vec<usize>,
+}
+impl Category {
+    pub fn new(name: &str, groups: &[usize]) -> Self {
+        Category {
+            name: name.to_owned(),
+            groups: groups.to_vec(),
+        }
+    }
+    // `shape` is the sizes of the groups of matching cards in a hand, largest first.
+    fn satisfied_by(&self, shape: &[usize]) -> bool {
+        self.groups.len() <= shape.len() && self.groups.iter().zip(shape).all(|(g, s)| s >= g)
+    }
+}
+
+impl Rules {
+    /// Part 1's rules: five cards, no wildcards.
+    pub fn standard() -> Rules {
+        Rules {
+            order: "23456789TJQKA".chars().collect(),
+            wildcards: Vec::new(),
+            categories: vec![
+                Category::new("high card", &[1]),
+                Category::new("one pair", &[2]),
+                Category::new("two pair", &[2, 2]),
+                Category::new("three of a kind", &[3]),
+                Category::new("full house", &[3, 2]),
+                Category::new("four of a kind", &[4]),
+                Category::new("five of a kind", &[5]),
+            ],
+            hand_size: 5,
+            wildcard_strategy: WildcardStrategy::Modal,
+        }
+    }
+
+    /// Makes `cards` wild. As with part 2's jokers, they become the weakest cards for tie-breaking.
+    pub fn with_wildcards(mut self, cards: &str) -> Rules {
+        let wild: Vec<char> = cards.chars().collect();
+        self.order.retain(|c| !wild.contains(c));
+        self.order.splice(0..0, wild.iter().copied());
+        self.wildcards.extend(wild);
+        self
+    }
+
+    /// Switches to exhaustive wildcard assignment if the modal heuristic gets any hand wrong.
+    pub fn verified(mut self) -> Rules {
+        if find_counterexample(&self).is_some() {
+            self.wildcard_strategy = WildcardStrategy::Exhaustive;
+        }
+        self
+    }
+
+    /// The category of `hand`, as an index into `categories` (or `None` if it satisfies none of them).
+    pub fn category(&self, hand: &str) -> anyhow::Result<Option<usize>> {
+        Ok(self.rank(hand)?.0)
+    }
+
+    // Hands sort by category, then card-by-card strength.
+    fn rank(&self, hand: &str) -> anyhow::Result<(Option<usize>, Vec<usize>)> {
+        if hand.chars().count() != self.hand_size {
+            bail!("{hand} should have {} cards", self.hand_size);
+        }
+        let strengths = hand
+            .chars()
+            .map(|c| {
+                self.order
+                    .iter()
+                    .position(|&o| o == c)
+                    .ok_or_else(|| anyhow!("unknown card {c} in {hand}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let category = match self.wildcard_strategy {
+            WildcardStrategy::Modal => self.modal_category(hand),
+            WildcardStrategy::Exhaustive => self.best_assignment(hand).0,
+        };
+        Ok((category, strengths))
+    }
+
+    fn categorize(&self, shape: &[usize]) -> Option<usize> {
+        self.categories.iter().rposition(|c| c.satisfied_by(shape))
+    }
+
+    // Coerces every wildcard into the modal non-wild card.
+    fn modal_category(&self, hand: &str) -> Option<usize> {
+        let (mut shape, wild) = self.shape(hand);
+        // It is technically possible that the hand is entirely wild.
+        match shape.first_mut() {
+            Some(modal) => *modal += wild,
+            None if wild > 0 => shape.push(wild),
+            None => {}
+        }
+        self.categorize(&shape)
+    }
+
+    /// The strongest category that `hand` can reach, and the hand with its wildcards replaced
+    /// to get there. Tries every assignment, so it's exponential in the number of wildcards.
+    pub fn best_assignment(&self, hand: &str) -> (Option<usize>, String) {
+        let wild: Vec<usize> = hand
+            .chars()
+            .positions(|c| self.wildcards.contains(&c))
+            .collect();
+        let labels: Vec<char> = self
+            .order
+            .iter()
+            .filter(|c| !self.wildcards.contains(c))
+            .copied()
+            .collect();
+        if wild.is_empty() || labels.is_empty() {
+            return (self.categorize(&self.shape(hand).0), hand.to_owned());
+        }
+        let mut best = (None, hand.to_owned());
+        for assignment in labels.into_iter().combinations_with_replacement(wild.len()) {
+            let mut cards: Vec<char> = hand.chars().collect();
+            for (&i, c) in wild.iter().zip(assignment) {
+                cards[i] = c;
+            }
+            let candidate: String = cards.into_iter().collect();
+            let category = self.categorize(&self.shape(&candidate).0);
+            if category > best.0 {
+                best = (category, candidate);
+            }
+        }
+        best
+    }
+
+    // The sizes of the groups of matching non-wild cards, largest first, and how many wildcards there are.
+    fn shape(&self, hand: &str) -> (Vec<usize>, usize) {
+        let mut tally: BTreeMap<char, usize> = BTreeMap::new();
+        let mut wild = 0;
+        for card in hand.chars() {
+            if self.wildcards.contains(&card) {
+                wild += 1;
+            } else {
+                *tally.entry(card).or_default() += 1;
+            }
+        }
+        let mut shape: Vec<usize> = tally.into_values().collect();
+        shape.sort_by(|a, b| b.cmp(a));
+        (shape, wild)
+    }
+}
+
+/// A hand where coercing the wildcards into the modal card is not optimal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    pub hand: String,
+    pub modal: Option<usize>,
+    pub best: Option<usize>,
+    /// `hand`, with its wildcards replaced by the cards that reach `best`.
+    pub assignment: String,
+}
+
+/// Checks the modal heuristic against every possible hand (order doesn't affect the
+/// category, so it's enough to check every multiset of cards).
+pub fn find_counterexample(rules: &Rules) -> Option<Counterexample> {
+    rules
+        .order
+        .iter()
+        .combinations_with_replacement(rules.hand_size)
+        .find_map(|cards| {
+            let hand: String = cards.into_iter().collect();
+            let modal = rules.modal_category(&hand);
+            let (best, assignment) = rules.best_assignment(&hand);
+            (modal != best).then_some(Counterexample {
+                hand,
+                modal,
+                best,
+                assignment,
+            })
+        })
+}
+
 #[derive(Debug, Copy, Clone)]
-struct Bid {
-    hand: [u8; 5],
+struct Bid<'a> {
+    hand: &'a str,
     amount: u64,
 }
 
-fn parse_bids(input: &str) -> anyhow::Result<Vec<Bid>> {
+fn parse_bids(input: &str) -> anyhow::Result<Vec<Bid<'_>>> {
     let (_, bids) = all_consuming(delimited(multispace0, bids_parser, multispace0))(input)
         .map_err(|err| anyhow!("could not parse {input}: {err}"))?;
     Ok(bids)
 }
 
-fn bids_parser(input: &str) -> IResult<&str, Vec<Bid>> {
+fn bids_parser(input: &str) -> IResult<&str, Vec<Bid<'_>>> {
     separated_list1(multispace1, bid_parser)(input)
 }
-fn bid_parser(input: &str) -> IResult<&str, Bid> {
-    let (input, (hand, amount)) = separated_pair(hand_parser, multispace1, num_parser)(input)?;
+fn bid_parser(input: &str) -> IResult<&str, Bid<'_>> {
+    let (input, (hand, amount)) = separated_pair(alphanumeric1, multispace1, num_parser)(input)?;
     Ok((input, Bid { hand, amount }))
 }
-fn hand_parser(input: &str) -> IResult<&str, [u8; 5]> {
-    let (input, cards) = count(card_parser, 5)(input)?;
-    Ok((input, cards.try_into().unwrap()))
-}
-fn card_parser(input: &str) -> IResult<&str, u8> {
-    alt((
-        value(14, tag("A")),
-        value(13, tag("K")),
-        value(12, tag("Q")),
-        value(11, tag("J")),
-        value(10, tag("T")),
-        value(9, tag("9")),
-        value(8, tag("8")),
-        value(7, tag("7")),
-        value(6, tag("6")),
-        value(5, tag("5")),
-        value(4, tag("4")),
-        value(3, tag("3")),
-        value(2, tag("2")),
-    ))(input)
-}
 fn num_parser(input: &str) -> IResult<&str, u64> {
     map_res(digit1, str::parse)(input)
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
-enum Kind {
-    HighCard,
-    OnePair,
-    TwoPair,
-    Triple,
-    FullHouse,
-    Quad,
-    Quint,
-}
+#[cfg(test)]
+mod test {
+    use super::*;
 
-fn categorize(hand: [u8; 5]) -> Kind {
-    compute_kind(tally(hand))
-}
-fn tally(hand: [u8; 5]) -> BTreeMap<u8, usize> {
-    let mut tally = BTreeMap::new();
-    for card in hand {
-        *tally.entry(card).or_default() += 1;
-    }
-    tally
-}
-fn compute_kind(t: BTreeMap<u8, usize>) -> Kind {
-    let mut counts: Vec<usize> = t.values().copied().collect();
-    counts.sort();
-    if counts == vec![5] {
-        return Kind::Quint;
-    }
-    if counts == vec![1, 4] {
-        return Kind::Quad;
+    fn category_name(rules: &Rules, hand: &str) -> String {
+        let idx = rules.category(hand).unwrap().unwrap();
+        rules.categories[idx].name.clone()
     }
-    if counts == vec![2, 3] {
-        return Kind::FullHouse;
+
+    #[test]
+    fn categorize_test() {
+        let rules = Rules::standard();
+        assert_eq!(category_name(&rules, "22222"), "five of a kind");
+        assert_eq!(category_name(&rules, "22322"), "four of a kind");
+        assert_eq!(category_name(&rules, "22323"), "full house");
+        assert_eq!(category_name(&rules, "22324"), "three of a kind");
+        assert_eq!(category_name(&rules, "22334"), "two pair");
+        assert_eq!(category_name(&rules, "22345"), "one pair");
+        assert_eq!(category_name(&rules, "23456"), "high card");
+        assert!(rules.category("2345").is_err());
+        assert!(rules.category("2345X").is_err());
     }
-    if counts == vec![1, 1, 3] {
-        return Kind::Triple;
+
+    #[test]
+    fn wildcard_test() {
+        let rules = Rules::standard().with_wildcards("J");
+        assert_eq!(rules.order[0], 'J');
+        assert_eq!(category_name(&rules, "QJJQ2"), "four of a kind");
+        assert_eq!(category_name(&rules, "JJJJJ"), "five of a kind");
+        assert_eq!(category_name(&rules, "T55J5"), "four of a kind");
+        assert_eq!(category_name(&rules, "2345J"), "one pair");
+
+        // Several distinct wildcards pool together.
+        let rules = Rules::standard().with_wildcards("JT");
+        assert_eq!(category_name(&rules, "2T3J4"), "three of a kind");
+        assert_eq!(category_name(&rules, "2TJ2T"), "five of a kind");
+        assert!(rules.rank("JAAAA").unwrap() < rules.rank("TAAAA").unwrap());
     }
-    if counts == vec![1, 2, 2] {
-        return Kind::TwoPair;
+
+    #[test]
+    fn modal_heuristic_is_optimal() {
+        assert_eq!(find_counterexample(&Rules::standard()), None);
+        assert_eq!(
+            find_counterexample(&Rules::standard().with_wildcards("J")),
+            None
+        );
+        assert_eq!(
+            find_counterexample(&Rules::standard().with_wildcards("J2T")),
+            None
+        );
     }
-    if counts == vec![1, 1, 1, 2] {
-        return Kind::OnePair;
+
+    #[test]
+    fn modal_heuristic_counterexample() {
+        // With three pair ranked above a full house, it's better to spread the wildcards out.
+        let mut rules = Rules::standard().with_wildcards("J");
+        rules.hand_size = 6;
+        rules
+            .categories
+            .insert(5, Category::new("three pair", &[2, 2, 2]));
+        let counterexample = find_counterexample(&rules).unwrap();
+        assert_eq!(
+            rules.categories[counterexample.best.unwrap()].name,
+            "three pair"
+        );
+        assert_eq!(
+            rules.categories[counterexample.modal.unwrap()].name,
+            "full house"
+        );
+
+        assert_eq!(category_name(&rules, "22334J"), "full house");
+        let rules = rules.verified();
+        assert_eq!(rules.wildcard_strategy, WildcardStrategy::Exhaustive);
+        assert_eq!(category_name(&rules, "22334J"), "three pair");
+        assert_eq!(rules.best_assignment("22334J").1, "223344",);
     }
-    Kind::HighCard
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn exhaustive_matches_modal_on_real_input() {
+        let mut rules = Rules::standard().with_wildcards("J");
+        rules.wildcard_strategy = WildcardStrategy::Exhaustive;
+        assert_eq!(
+            winnings(
+                &std::fs::read_to_string("data/day07.input").unwrap(),
+                &rules
+            )
+            .unwrap(),
+            249515436,
+        );
+    }
 
     #[test]
-    fn categorize_test() {
-        assert_eq!(categorize([1, 1, 1, 1, 1]), Kind::Quint);
-        assert_eq!(categorize([1, 1, 2, 1, 1]), Kind::Quad);
-        assert_eq!(categorize([1, 1, 2, 1, 2]), Kind::FullHouse);
-        assert_eq!(categorize([1, 1, 2, 1, 3]), Kind::Triple);
-        assert_eq!(categorize([1, 1, 2, 2, 3]), Kind::TwoPair);
-        assert_eq!(categorize([1, 1, 2, 3, 4]), Kind::OnePair);
-        assert_eq!(categorize([1, 2, 3, 4, 5]), Kind::HighCard);
+    fn larger_hands() {
+        let mut rules = Rules::standard();
+        rules.hand_size = 7;
+        rules
+            .categories
+            .insert(5, Category::new("three pair", &[2, 2, 2]));
+        rules.categories.push(Category::new("six of a kind", &[6]));
+        assert_eq!(category_name(&rules, "2233445"), "three pair");
+        assert_eq!(category_name(&rules, "2223334"), "full house");
+        assert_eq!(category_name(&rules, "2222223"), "six of a kind");
+        // Four of a kind still beats three pair, so the second hand ranks higher.
+        assert_eq!(
+            winnings("2233445 10\n2222345 3", &rules).unwrap(),
+            10 + 2 * 3
+        );
+        assert!(winnings("22334 10", &rules).is_err());
     }
 
     const SAMPLE_INPUT: &str = "
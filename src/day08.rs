@@ -100,6 +100,7 @@ before you're only on nodes that end with Z?
 
 use std::collections::HashMap;
 
+use crate::cycle;
 use anyhow::{anyhow, bail, Context};
 use nom::{
     branch::alt,
@@ -183,44 +184,42 @@ pub fn part2(input: &str) -> anyhow::Result<usize> {
         .ok_or(anyhow!("graph has no __A nodes"))
 }
 
-fn compute_cycle(
-    start: &str,
+fn compute_cycle<'a>(
+    start: &'a str,
     directions: &[Direction],
-    graph: &HashMap<String, (String, String)>,
+    graph: &'a HashMap<String, (String, String)>,
 ) -> anyhow::Result<usize> {
-    let mut cur = start;
-
     // In order to tell whether we're actually in a cycle, we need to have been at the same node,
     // and in the same position in our directions loop.
-    let mut vis: HashMap<&str, HashMap<usize, usize>> = HashMap::new();
-    let mut iter = directions.iter().cycle().enumerate();
-    loop {
-        let (i, &dir) = iter.next().unwrap();
-        let pos = i % directions.len();
-        if let Some(&prev) = vis.get(cur).and_then(|v| v.get(&pos)) {
-            // We hit a cycle!
-            let cycle_length = i - prev;
-            let winners: Vec<usize> = vis
-                .into_iter()
-                .filter(|(k, _v)| k.ends_with('Z'))
-                .flat_map(|(_k, v)| v.into_values())
-                .collect();
-            let effective_cycle_length = winners
-                .iter()
-                .copied()
-                .reduce(gcd)
-                .context("the cycle must contain __Z nodes")?;
-            if effective_cycle_length * winners.len() != cycle_length {
-                bail!("we ony handle cases where the exit condition can be expressed as `t = 0 (mod m)`, and a cycle of {cycle_length} w/ __Z nodes at {winners:?} cannot");
-            }
-            return Ok(effective_cycle_length);
-        }
-        vis.entry(cur).or_default().insert(pos, i);
-        cur = match dir {
-            Direction::Left => graph.get(cur).unwrap().0.as_str(),
-            Direction::Right => graph.get(cur).unwrap().1.as_str(),
+    let step = |(cur, pos): &mut (&'a str, usize)| {
+        let (l, r) = graph.get(*cur).unwrap();
+        *cur = match directions[*pos] {
+            Direction::Left => l,
+            Direction::Right => r,
         };
+        *pos = (*pos + 1) % directions.len();
+    };
+    let cycle = cycle::brent(&(start, 0), step);
+    let cycle_length = cycle.lambda;
+
+    // Every time we were at a __Z node before the walk first came back around.
+    let mut state = (start, 0);
+    let mut winners = Vec::new();
+    for i in 0..cycle.mu + cycle.lambda {
+        if state.0.ends_with('Z') {
+            winners.push(i);
+        }
+        step(&mut state);
+    }
+    let effective_cycle_length = winners
+        .iter()
+        .copied()
+        .reduce(gcd)
+        .context("the cycle must contain __Z nodes")?;
+    if effective_cycle_length * winners.len() != cycle_length {
+        bail!("we ony handle cases where the exit condition can be expressed as `t = 0 (mod m)`, and a cycle of {cycle_length} w/ __Z nodes at {winners:?} cannot");
     }
+    Ok(effective_cycle_length)
 }
 
 fn gcd(m: usize, n: usize) -> usize {
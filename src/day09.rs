@@ -95,52 +95,58 @@ use nom::{
     IResult,
 };
 
-pub fn part1(input: &str) -> anyhow::Result<i32> {
+use crate::sequence::Polynomial;
+
+pub fn part1(input: &str) -> anyhow::Result<i128> {
     let readings = parse_input(input)?;
 
-    let mut total = 0;
+    let mut total: i128 = 0;
     for samples in readings {
-        total += extrapolate(&samples);
+        let next = predict(&samples, samples.len() as i128)?;
+        total = total
+            .checked_add(next)
+            .ok_or_else(|| anyhow!("total overflows i128"))?;
     }
     Ok(total)
 }
 
-pub fn part2(input: &str) -> anyhow::Result<i32> {
+pub fn part2(input: &str) -> anyhow::Result<i128> {
     let readings = parse_input(input)?;
 
-    let mut total = 0;
+    let mut total: i128 = 0;
     for samples in readings {
-        total += extrapolate_back(&samples);
+        let prev = predict(&samples, -1)?;
+        total = total
+            .checked_add(prev)
+            .ok_or_else(|| anyhow!("total overflows i128"))?;
     }
     Ok(total)
 }
 
-fn extrapolate(samples: &[i32]) -> i32 {
-    if samples.iter().all(|&x| x == 0) {
-        return 0;
-    }
-    let diffs: Vec<i32> = samples.windows(2).map(|w| w[1] - w[0]).collect();
-    samples.last().unwrap() + extrapolate(&diffs)
+/// The value at `index` in the sequence that `samples` start, where the first sample is at 0.
+/// Negative indices extrapolate backwards.
+pub fn predict(samples: &[i128], index: i128) -> anyhow::Result<i128> {
+    Polynomial::fit(samples)?.at(index)
 }
 
-fn extrapolate_back(samples: &[i32]) -> i32 {
-    if samples.iter().all(|&x| x == 0) {
-        return 0;
-    }
-    let diffs: Vec<i32> = samples.windows(2).map(|w| w[1] - w[0]).collect();
-    samples.first().unwrap() - extrapolate_back(&diffs)
+/// The degree of the polynomial behind each reading, or `None` if it's all zeros.
+pub fn degrees(input: &str) -> anyhow::Result<Vec<Option<usize>>> {
+    parse_input(input)?
+        .iter()
+        .map(|samples| Ok(Polynomial::fit(samples)?.degree()))
+        .collect()
 }
 
-fn parse_input(input: &str) -> anyhow::Result<Vec<Vec<i32>>> {
+fn parse_input(input: &str) -> anyhow::Result<Vec<Vec<i128>>> {
     let (_, readings) = all_consuming(delimited(multispace0, readings_parser, multispace0))(input)
         .map_err(|err| anyhow!("could not parse {input}: {err}"))?;
     Ok(readings)
 }
 
-fn readings_parser(input: &str) -> IResult<&str, Vec<Vec<i32>>> {
+fn readings_parser(input: &str) -> IResult<&str, Vec<Vec<i128>>> {
     separated_list1(newline, samples_parser)(input)
 }
-fn samples_parser(input: &str) -> IResult<&str, Vec<i32>> {
+fn samples_parser(input: &str) -> IResult<&str, Vec<i128>> {
     delimited(
         space0,
         separated_list1(space1, map_res(is_a("0123456789-"), str::parse)),
@@ -174,6 +180,34 @@ mod test {
     fn part1_sample_input() {
         assert_eq!(part1(SAMPLE_INPUT).unwrap(), 114);
     }
+    #[test]
+    fn predict_any_offset() {
+        let samples = [10, 13, 16, 21, 30, 45];
+        assert_eq!(predict(&samples, 6).unwrap(), 68);
+        assert_eq!(predict(&samples, -1).unwrap(), 5);
+        assert_eq!(predict(&samples, 8).unwrap(), 146);
+        assert_eq!(predict(&samples, -3).unwrap(), -19);
+        assert_eq!(predict(&samples, 2).unwrap(), 16);
+    }
+
+    #[test]
+    fn degrees_sample_input() {
+        assert_eq!(
+            degrees(SAMPLE_INPUT).unwrap(),
+            vec![Some(1), Some(2), Some(3)]
+        );
+    }
+
+    #[test]
+    fn wide_readings_dont_wrap() {
+        // 2^x, fitted by a degree 39 polynomial whose Newton coefficients are all 1. A table
+        // of i32s would overflow on the samples alone.
+        let samples: Vec<i128> = (0..40).map(|x| 1 << x).collect();
+        assert_eq!(Polynomial::fit(&samples).unwrap().degree(), Some(39));
+        // The next value is 2^40 - 1, since the 40th difference is assumed to be zero.
+        assert_eq!(predict(&samples, 40).unwrap(), (1 << 40) - 1);
+    }
+
     #[test]
     fn part1_real_input() {
         assert_eq!(
@@ -1,6 +1,6 @@
 use std::fmt::Write;
 
-use crate::grid::Grid;
+use crate::{grid::Grid, polygon::Polygon};
 use anyhow::{anyhow, bail};
 
 /*
@@ -258,30 +258,14 @@ pub fn part2(input: &str) -> anyhow::Result<i32> {
         bail!("no closed loop")
     };
 
-    // Consider the entire path to be a large polygon. Use the "surveyor's formula" to compute its area:
-    //   A = 1/2 * Sum[ (y[i] + y[i+1]) * (x[i] - x[i+1]) ] over all edges.
-    // In our case that will give us a bit too much area.
-    // Consider the simple input:
-    //   F7
-    //   LJ
-    // which has no internal squares at all. We're modeling this as a polygon with points at
-    //   {(0, 0), (1, 0), (1, 1), (0, 1) }
-    // which has an area of 1.
-    // If we want to know how many interior points it has, we can subtract off the area within a small region
-    // of the perimeter. I imagine there's some clever way to show that this always comes up to be a bit too
-    // large, but empirically (Area - Perimeter + 1) matches the flood-fill algorithm.
-    let mut area = 0;
-    let mut perimeter = 0;
-    let mut prev = path.last().copied().unwrap();
-    for cur in path {
-        let (x0, y0) = prev;
-        let (x1, y1) = cur;
-        area += (y0 + y1) * (x1 - x0);
-        perimeter += 1;
-        prev = cur;
-    }
-    dbg!(area, perimeter);
-    Ok(area.abs() / 2 - perimeter / 2 + 1)
+    // The loop runs through the centers of its tiles, so the enclosed tiles are exactly the
+    // lattice points strictly inside it. Flip the rows so that y points up, as drawn.
+    let polygon = Polygon::new(
+        path.into_iter()
+            .map(|(i, j)| (j as i64, -(i as i64)))
+            .collect(),
+    );
+    Ok(i32::try_from(polygon.interior_points()?)?)
 }
 
 fn extract_loop(
@@ -101,9 +101,9 @@ Now, instead of the expansion you did before, make each empty row or column one
 Starting with the same initial image, expand the universe according to these new rules, then find the length of the shortest path between every pair of galaxies. What is the sum of these lengths?
 */
 
-use std::cmp;
+use std::{collections::BTreeSet, ops::Range};
 
-use itertools::Itertools;
+use anyhow::bail;
 
 use crate::grid::Grid;
 
@@ -117,33 +117,254 @@ pub fn part2(input: &str) -> anyhow::Result<usize> {
     Ok(solve(&grid, 999_999))
 }
 
+// `expansion` is the number of rows (or columns) added for each empty one.
 fn solve(grid: &Grid<u8>, expansion: usize) -> usize {
-    let stars: Vec<(i32, i32)> = grid
-        .enumerate()
-        .filter(|(_, &cell)| cell == b'#')
-        .map(|(pos, _)| pos)
-        .collect();
-    let empty_rows: Vec<i32> = (0..grid.height())
-        .filter(|&i| (0..grid.width()).all(|j| grid.get(i, j).copied() != Some(b'#')))
-        .collect();
-    let empty_cols: Vec<i32> = (0..grid.width())
-        .filter(|&j| (0..grid.height()).all(|i| grid.get(i, j).copied() != Some(b'#')))
-        .collect();
-
-    let distances: Vec<usize> = stars
-        .into_iter()
-        .tuple_combinations()
-        .map(|((i0, j0), (i1, j1))| {
-            let (ilo, ihi) = (cmp::min(i0, i1), cmp::max(i0, i1));
-            let (jlo, jhi) = (cmp::min(j0, j1), cmp::max(j0, j1));
-            (ihi - ilo) as usize
-                + (jhi - jlo) as usize
-                + expansion * empty_rows.iter().filter(|&&i| ilo < i && i < ihi).count()
-                + expansion * empty_cols.iter().filter(|&&j| jlo < j && j < jhi).count()
-        })
-        .collect();
-
-    distances.into_iter().sum()
+    Universe::new(grid, Expansion::uniform(expansion + 1)).total_distance()
+}
+
+/// How many rows (or columns) each empty row (or column) becomes. The puzzle's
+/// expansions are `Expansion::uniform(2)` and `Expansion::uniform(1_000_000)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expansion {
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Expansion {
+    pub fn uniform(factor: usize) -> Self {
+        Expansion {
+            rows: factor,
+            cols: factor,
+        }
+    }
+}
+
+/// Two galaxies, by index, and the distance between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pair {
+    pub a: usize,
+    pub b: usize,
+    pub distance: usize,
+}
+
+/// The galaxies in an image, at their coordinates after expansion. Galaxies are numbered
+/// from 0 in reading order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Universe {
+    height: usize,
+    width: usize,
+    galaxies: Vec<(usize, usize)>,
+}
+
+impl Universe {
+    pub fn new(grid: &Grid<u8>, expansion: Expansion) -> Self {
+        let mut occupied_rows = vec![false; grid.height() as usize];
+        let mut occupied_cols = vec![false; grid.width() as usize];
+        let stars: Vec<(usize, usize)> = grid
+            .enumerate()
+            .filter(|(_, &cell)| cell == b'#')
+            .map(|((i, j), _)| (i as usize, j as usize))
+            .collect();
+        for &(i, j) in &stars {
+            occupied_rows[i] = true;
+            occupied_cols[j] = true;
+        }
+        let (rows, height) = expand_axis(&occupied_rows, expansion.rows);
+        let (cols, width) = expand_axis(&occupied_cols, expansion.cols);
+        Universe {
+            height,
+            width,
+            galaxies: stars.into_iter().map(|(i, j)| (rows[i], cols[j])).collect(),
+        }
+    }
+
+    pub fn galaxies(&self) -> &[(usize, usize)] {
+        &self.galaxies
+    }
+
+    /// The height and width of the whole expanded image, including any empty space at the edges.
+    pub fn size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    /// The rows and columns spanned by the galaxies, or `None` if there aren't any.
+    pub fn bounding_box(&self) -> Option<(Range<usize>, Range<usize>)> {
+        let (rows, cols): (Vec<usize>, Vec<usize>) = self.galaxies.iter().copied().unzip();
+        let (&top, &bottom) = (rows.iter().min()?, rows.iter().max()?);
+        let (&left, &right) = (cols.iter().min()?, cols.iter().max()?);
+        Some((top..bottom + 1, left..right + 1))
+    }
+
+    /// Groups the galaxies so that each one is within `max_distance` of some other galaxy in
+    /// its cluster. Clusters are in order of their lowest-numbered galaxy. Checks every pair,
+    /// so it's quadratic in the number of galaxies.
+    pub fn clusters(&self, max_distance: usize) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.galaxies.len()];
+        let mut acc: Vec<Vec<usize>> = Vec::new();
+        for seed in 0..self.galaxies.len() {
+            if seen[seed] {
+                continue;
+            }
+            seen[seed] = true;
+            let mut members = vec![seed];
+            let mut idx = 0;
+            while idx < members.len() {
+                let g = members[idx];
+                for (other, seen) in seen.iter_mut().enumerate() {
+                    if !*seen && self.distance(g, other) <= max_distance {
+                        *seen = true;
+                        members.push(other);
+                    }
+                }
+                idx += 1;
+            }
+            members.sort_unstable();
+            acc.push(members);
+        }
+        acc
+    }
+
+    /// Materializes the expanded image. Expansion factors can make this enormous, so it
+    /// fails if the image would have more than `MAX_GRID_CELLS` cells.
+    pub fn to_grid(&self) -> anyhow::Result<Grid<u8>> {
+        match self.height.checked_mul(self.width) {
+            Some(cells) if cells <= MAX_GRID_CELLS => {}
+            _ => bail!(
+                "expanded image is {}x{}, which is too big to materialize",
+                self.height,
+                self.width
+            ),
+        }
+        let mut rows = vec![vec![b'.'; self.width]; self.height];
+        for &(i, j) in &self.galaxies {
+            rows[i][j] = b'#';
+        }
+        Grid::new(rows)
+    }
+
+    /// The expanded image, drawn the same way as the input.
+    pub fn render(&self) -> anyhow::Result<String> {
+        let grid = self.to_grid()?;
+        Ok(grid
+            .rows()
+            .iter()
+            .map(|row| String::from_utf8_lossy(row) + "\n")
+            .collect())
+    }
+
+    /// The length of the shortest path between two galaxies. Panics if either doesn't exist.
+    pub fn distance(&self, a: usize, b: usize) -> usize {
+        let ((i0, j0), (i1, j1)) = (self.galaxies[a], self.galaxies[b]);
+        i0.abs_diff(i1) + j0.abs_diff(j1)
+    }
+
+    /// The sum of the distances between every pair of galaxies.
+    pub fn total_distance(&self) -> usize {
+        // Manhattan distance splits by axis, and along a sorted axis the k-th coordinate is
+        // the larger one in exactly k pairs.
+        let mut rows: Vec<usize> = self.galaxies.iter().map(|&(i, _)| i).collect();
+        let mut cols: Vec<usize> = self.galaxies.iter().map(|&(_, j)| j).collect();
+        rows.sort_unstable();
+        cols.sort_unstable();
+        [rows, cols]
+            .iter()
+            .map(|axis| {
+                let mut prefix = 0;
+                let mut total = 0;
+                for (k, &x) in axis.iter().enumerate() {
+                    total += k * x - prefix;
+                    prefix += x;
+                }
+                total
+            })
+            .sum()
+    }
+
+    /// The two galaxies closest to each other, or `None` if there are fewer than two.
+    pub fn nearest_pair(&self) -> Option<Pair> {
+        let mut order: Vec<usize> = (0..self.galaxies.len()).collect();
+        order.sort_by_key(|&g| self.galaxies[g]);
+        // Sweep down the rows, keeping a window (ordered by column) of the galaxies that are
+        // fewer rows above the current one than the best distance so far.
+        let mut best: Option<Pair> = None;
+        let mut window: BTreeSet<(usize, usize)> = BTreeSet::new();
+        let mut oldest = 0;
+        for &g in &order {
+            let (i, j) = self.galaxies[g];
+            let reach = best.map_or(usize::MAX, |p| p.distance);
+            while oldest < order.len() {
+                let old = order[oldest];
+                if i - self.galaxies[old].0 < reach {
+                    break;
+                }
+                window.remove(&(self.galaxies[old].1, old));
+                oldest += 1;
+            }
+            let lo = (j.saturating_sub(reach), 0);
+            let hi = (j.saturating_add(reach), usize::MAX);
+            for &(_, other) in window.range(lo..=hi) {
+                let distance = self.distance(g, other);
+                if best.is_none_or(|p| distance < p.distance) {
+                    best = Some(Pair::new(g, other, distance));
+                }
+            }
+            window.insert((j, g));
+        }
+        best
+    }
+
+    /// The two galaxies farthest from each other, or `None` if there are fewer than two.
+    pub fn farthest_pair(&self) -> Option<Pair> {
+        if self.galaxies.len() < 2 {
+            return None;
+        }
+        // |di| + |dj| is the larger of |di + dj| and |di - dj|, so the farthest pair is the
+        // one that's most spread out along one of the diagonals.
+        let diagonals: [fn((usize, usize)) -> i128; 2] = [
+            |(i, j)| i as i128 + j as i128,
+            |(i, j)| i as i128 - j as i128,
+        ];
+        diagonals
+            .iter()
+            .map(|diagonal| {
+                let key = |&g: &usize| diagonal(self.galaxies[g]);
+                let lo = (0..self.galaxies.len()).min_by_key(key).unwrap();
+                let hi = (0..self.galaxies.len()).max_by_key(key).unwrap();
+                Pair::new(lo, hi, self.distance(lo, hi))
+            })
+            .max_by_key(|p| p.distance)
+    }
+}
+
+impl Pair {
+    fn new(a: usize, b: usize, distance: usize) -> Self {
+        Pair {
+            a: a.min(b),
+            b: a.max(b),
+            distance,
+        }
+    }
+}
+
+/// The most cells `Universe::to_grid` will allocate.
+pub const MAX_GRID_CELLS: usize = 1 << 24;
+
+/// Parses an image and expands it.
+pub fn expand(input: &str, expansion: Expansion) -> anyhow::Result<Universe> {
+    let grid = parse_input(input)?;
+    Ok(Universe::new(&grid, expansion))
+}
+
+// Where each row (or column) ends up, given which ones have galaxies in them, and the
+// expanded length of the whole axis.
+fn expand_axis(occupied: &[bool], factor: usize) -> (Vec<usize>, usize) {
+    let mut acc = Vec::with_capacity(occupied.len());
+    let mut next = 0;
+    for &o in occupied {
+        acc.push(next);
+        next += if o { 1 } else { factor };
+    }
+    (acc, next)
 }
 
 fn parse_input(input: &str) -> anyhow::Result<Grid<u8>> {
@@ -158,6 +379,8 @@ fn parse_input(input: &str) -> anyhow::Result<Grid<u8>> {
 
 #[cfg(test)]
 mod test {
+    use itertools::Itertools;
+
     use super::*;
 
     const SAMPLE_INPUT: &str = "
@@ -200,6 +423,109 @@ mod test {
         assert_eq!(solve(&grid, 99), 8410);
     }
 
+    #[test]
+    fn distance_sample_input() {
+        let grid = parse_input(SAMPLE_INPUT).unwrap();
+        let universe = Universe::new(&grid, Expansion::uniform(2));
+        assert_eq!(universe.galaxies().len(), 9);
+        assert_eq!(universe.galaxies()[0], (0, 4));
+        // The puzzle numbers galaxies from 1.
+        assert_eq!(universe.distance(4, 8), 9);
+        assert_eq!(universe.distance(0, 6), 15);
+        assert_eq!(universe.distance(2, 5), 17);
+        assert_eq!(universe.distance(7, 8), 5);
+    }
+
+    #[test]
+    fn render_sample_input() {
+        let universe = expand(SAMPLE_INPUT, Expansion::uniform(2)).unwrap();
+        assert_eq!(universe.size(), (12, 13));
+        assert_eq!(
+            universe.render().unwrap(),
+            "\
+            ....#........\n\
+            .........#...\n\
+            #............\n\
+            .............\n\
+            .............\n\
+            ........#....\n\
+            .#...........\n\
+            ............#\n\
+            .............\n\
+            .............\n\
+            .........#...\n\
+            #....#.......\n"
+        );
+        // The same image, as seen by the distance formula.
+        let grid = universe.to_grid().unwrap();
+        assert_eq!(
+            Universe::new(&grid, Expansion::uniform(1)).total_distance(),
+            374
+        );
+
+        let huge = expand(SAMPLE_INPUT, Expansion::uniform(1_000_000)).unwrap();
+        assert!(huge.to_grid().is_err());
+        assert_eq!(huge.bounding_box(), Some((0..2_000_008, 0..3_000_007)));
+    }
+
+    #[test]
+    fn clusters_sample_input() {
+        let universe = expand(SAMPLE_INPUT, Expansion::uniform(2)).unwrap();
+        assert_eq!(universe.clusters(0).len(), 9);
+        assert_eq!(
+            universe.clusters(usize::MAX),
+            vec![(0..9).collect::<Vec<_>>()]
+        );
+        assert_eq!(
+            universe.clusters(5),
+            vec![vec![0], vec![1, 3], vec![2, 4], vec![5], vec![6, 7, 8]],
+        );
+    }
+
+    fn brute_force(universe: &Universe) -> (usize, Option<Pair>, Option<Pair>) {
+        let pairs: Vec<Pair> = (0..universe.galaxies().len())
+            .tuple_combinations()
+            .map(|(a, b)| Pair::new(a, b, universe.distance(a, b)))
+            .collect();
+        (
+            pairs.iter().map(|p| p.distance).sum(),
+            pairs.iter().copied().min_by_key(|p| p.distance),
+            pairs.iter().copied().max_by_key(|p| p.distance),
+        )
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        let input = std::fs::read_to_string("data/day11.input").unwrap();
+        for (grid, expansion) in itertools::iproduct!(
+            [
+                parse_input(SAMPLE_INPUT).unwrap(),
+                parse_input(&input).unwrap()
+            ],
+            [
+                Expansion::uniform(0),
+                Expansion::uniform(2),
+                Expansion { rows: 1, cols: 10 },
+                Expansion {
+                    rows: 1_000_000,
+                    cols: 3,
+                },
+            ]
+        ) {
+            let universe = Universe::new(&grid, expansion);
+            let (total, nearest, farthest) = brute_force(&universe);
+            assert_eq!(universe.total_distance(), total, "{expansion:?}");
+            // There may be ties, so only the distances need to agree.
+            let distance = |p: Option<Pair>| p.map(|p| p.distance);
+            assert_eq!(distance(universe.nearest_pair()), distance(nearest));
+            assert_eq!(distance(universe.farthest_pair()), distance(farthest));
+            for p in [universe.nearest_pair(), universe.farthest_pair()] {
+                let p = p.unwrap();
+                assert_eq!(universe.distance(p.a, p.b), p.distance);
+            }
+        }
+    }
+
     #[test]
     fn part2_real_input() {
         assert_eq!(
@@ -1,4 +1,6 @@
-use anyhow::anyhow;
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{anyhow, bail};
 use nom::{
     bytes::complete::{is_a, tag},
     character::complete::{digit1, space1},
@@ -6,6 +8,7 @@ use nom::{
     multi::separated_list1,
     IResult,
 };
+use rand::Rng;
 
 /*
 --- Day 12: Hot Springs ---
@@ -133,42 +136,250 @@ After unfolding, adding all of the possible arrangement counts together produces
 Unfold your condition records; what is the new sum of possible arrangement counts?
 */
 pub fn part1(input: &str) -> anyhow::Result<usize> {
-    let records = parse_input(input)?;
-    Ok(records.iter().map(count_arrangements).sum())
+    part1_with(input, Strategy::Dp1d)
 }
 
 pub fn part2(input: &str) -> anyhow::Result<usize> {
+    part2_with(input, Strategy::Dp1d)
+}
+
+pub fn part1_with(input: &str, strategy: Strategy) -> anyhow::Result<usize> {
+    total_with(parse_input(input)?, strategy)
+}
+
+pub fn part2_with(input: &str, strategy: Strategy) -> anyhow::Result<usize> {
     let records = parse_input(input)?;
-    Ok(records
-        .iter()
-        .map(|r| {
-            let mut unfolded = Record {
-                data: Vec::new(),
-                counts: Vec::new(),
-            };
-            for i in 0..5 {
-                if i > 0 {
-                    unfolded.data.push(b'?');
-                }
-                unfolded.data.extend_from_slice(&r.data);
-                unfolded.counts.extend_from_slice(&r.counts);
-            }
-            count_arrangements(&unfolded)
+    total_with(records.iter().map(|r| r.unfold(5)), strategy)
+}
+
+fn total_with(
+    records: impl IntoIterator<Item = Record>,
+    strategy: Strategy,
+) -> anyhow::Result<usize> {
+    records.into_iter().try_fold(0usize, |total, r| {
+        total
+            .checked_add(strategy.count_arrangements(&r)?)
+            .ok_or_else(|| anyhow!("total arrangement count overflows usize"))
+    })
+}
+
+/// Part 2, but unfolding every record `k` times instead of 5.
+pub fn part2_unfolded(input: &str, k: usize) -> anyhow::Result<u128> {
+    let records = parse_input(input)?;
+    records.iter().try_fold(0u128, |total, r| {
+        total
+            .checked_add(count_arrangements_unfolded(r, k)?)
+            .ok_or_else(|| anyhow!("total arrangement count overflows u128"))
+    })
+}
+
+/// The largest `k` that [`count_arrangements_unfolded`] accepts.
+pub const MAX_UNFOLD: usize = 1000;
+
+/// Counts the arrangements of `r` unfolded `k` times. The counts grow exponentially
+/// in `k`, so this fails (rather than wrapping) once they no longer fit in a `u128`.
+///
+/// Damaged segments can straddle the `?` between copies, so the copies aren't
+/// independent; this is the same 1D DP as part 2, run over the whole unfolded record.
+/// That means building all `k` copies, and time quadratic in `k`, so `k` above
+/// [`MAX_UNFOLD`] is an error even where the count itself would fit.
+pub fn count_arrangements_unfolded(r: &Record, k: usize) -> anyhow::Result<u128> {
+    if k > MAX_UNFOLD {
+        bail!("can't unfold {k} times, the most is {MAX_UNFOLD}");
+    }
+    dp1d::<u128>(&r.unfold(k)).ok_or_else(|| {
+        anyhow!(
+            "arrangement count for {} unfolded {k} times overflows u128",
+            String::from_utf8_lossy(&r.data)
+        )
+    })
+}
+
+/// The approaches described in `doc/day12.md`, from slowest to fastest. They all agree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Use-it-or-lose-it recursion. Exponential, so it's only practical for part 1.
+    Recursive,
+    /// The same recursion, memoized on how much of the data and counts are left.
+    Memoized,
+    /// Dynamic programming over the full (data x counts) table.
+    Dp2d,
+    /// Dynamic programming that only keeps one column of the table around.
+    Dp1d,
+}
+impl Strategy {
+    pub const ALL: [Strategy; 4] = [
+        Strategy::Recursive,
+        Strategy::Memoized,
+        Strategy::Dp2d,
+        Strategy::Dp1d,
+    ];
+
+    fn count_arrangements(self, r: &Record) -> anyhow::Result<usize> {
+        let count = match self {
+            Strategy::Recursive => count_recursive(r),
+            Strategy::Memoized => count_memoized(r),
+            Strategy::Dp2d => count_dp2d(r),
+            Strategy::Dp1d => dp1d(r),
+        };
+        count.ok_or_else(|| {
+            anyhow!(
+                "arrangement count for {} overflows usize",
+                String::from_utf8_lossy(&r.data)
+            )
         })
-        .sum())
+    }
+}
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Strategy::ALL
+            .into_iter()
+            .find(|strategy| format!("{strategy:?}").eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unknown strategy {s}, expected one of {:?}", Strategy::ALL))
+    }
+}
+
+fn count_recursive<T: Count>(r: &Record) -> Option<T> {
+    let mut total = T::ZERO;
+    count_helper(&mut total, &r.data, &r.counts)?;
+    Some(total)
+}
+// Returns `None` if `total` overflows.
+fn count_helper<T: Count>(total: &mut T, data: &[u8], counts: &[usize]) -> Option<()> {
+    if data.is_empty() {
+        if counts.is_empty() {
+            *total = total.add(T::ONE)?;
+        }
+        return Some(());
+    }
+    if counts.is_empty() {
+        if !data.contains(&b'#') {
+            *total = total.add(T::ONE)?;
+        }
+        return Some(());
+    }
+    if data[0] != b'#' {
+        // This is either ground, or unknown. Try skipping it.
+        count_helper(total, &data[1..], counts)?;
+    }
+    if data[0] != b'.' {
+        // This is either damaged, or unknown. Try consuming it.
+        if let Some(next) = remove_prefix(data, counts[0]) {
+            count_helper(total, next, &counts[1..])?;
+        }
+    }
+    Some(())
+}
+
+// Consumes a damaged segment of length `count` (plus the undamaged cell after it, if any).
+fn remove_prefix(data: &[u8], count: usize) -> Option<&[u8]> {
+    if data.len() < count || data[..count].contains(&b'.') {
+        return None;
+    }
+    if data.len() == count {
+        return Some(&data[count..]);
+    }
+    if data[count] == b'#' {
+        return None;
+    }
+    Some(&data[count + 1..])
 }
 
-fn count_arrangements(r: &Record) -> usize {
+fn count_memoized<T: Count>(r: &Record) -> Option<T> {
+    // We only ever recurse on suffixes, so (data.len(), counts.len()) identifies a call.
+    fn helper<T: Count>(
+        memo: &mut HashMap<(usize, usize), T>,
+        data: &[u8],
+        counts: &[usize],
+    ) -> Option<T> {
+        if counts.is_empty() {
+            return Some(if data.contains(&b'#') {
+                T::ZERO
+            } else {
+                T::ONE
+            });
+        }
+        if data.is_empty() {
+            return Some(T::ZERO);
+        }
+        if let Some(&total) = memo.get(&(data.len(), counts.len())) {
+            return Some(total);
+        }
+        let mut total = T::ZERO;
+        if data[0] != b'#' {
+            total = total.add(helper(memo, &data[1..], counts)?)?;
+        }
+        if data[0] != b'.' {
+            if let Some(next) = remove_prefix(data, counts[0]) {
+                total = total.add(helper(memo, next, &counts[1..])?)?;
+            }
+        }
+        memo.insert((data.len(), counts.len()), total);
+        Some(total)
+    }
+    helper(&mut HashMap::new(), &r.data, &r.counts)
+}
+
+fn count_dp2d<T: Count>(r: &Record) -> Option<T> {
+    // D x C matrix of counts
+    // memo[d][c] = f(data[..d], counts[..c])
+    let mut open = vec![vec![T::ZERO; r.counts.len() + 1]; r.data.len() + 1];
+    let mut closed = vec![vec![T::ZERO; r.counts.len() + 1]; r.data.len() + 1];
+
+    // The empty input always matches
+    open[0][0] = T::ONE;
+    for d in 1..=r.data.len() {
+        if r.data[d - 1] != b'#' {
+            open[d][0] = open[d - 1][0];
+        };
+        for c in 1..=r.counts.len() {
+            if r.data[d - 1] != b'#' {
+                open[d][c] = open[d - 1][c].add(closed[d - 1][c])?;
+            }
+            let count = r.counts[c - 1];
+            if d >= count && r.data[..d].iter().rev().take(count).all(|&ch| ch != b'.') {
+                closed[d][c] = open[d - count][c - 1];
+            }
+        }
+    }
+    open[r.data.len()][r.counts.len()].add(closed[r.data.len()][r.counts.len()])
+}
+
+// Lets every strategy count in `usize` for the puzzle answers, and the DP count in `u128` for
+// bigger unfoldings. Either way, counting stops with `None` rather than wrapping.
+trait Count: Copy {
+    const ZERO: Self;
+    const ONE: Self;
+    fn add(self, other: Self) -> Option<Self>;
+}
+impl Count for usize {
+    const ZERO: Self = 0;
+    const ONE: Self = 1;
+    fn add(self, other: Self) -> Option<Self> {
+        self.checked_add(other)
+    }
+}
+impl Count for u128 {
+    const ZERO: Self = 0;
+    const ONE: Self = 1;
+    fn add(self, other: Self) -> Option<Self> {
+        self.checked_add(other)
+    }
+}
+
+fn dp1d<T: Count>(r: &Record) -> Option<T> {
     // How many arrangements are there which end with a damaged segment (and are therefore not eligible for another damaged segment)?
-    let mut damaged = vec![0; r.data.len() + 1];
+    let mut damaged = vec![T::ZERO; r.data.len() + 1];
     // How many arragements are there which do NOT end with a damaged segment at `i`?
-    let mut undamaged = vec![0; r.data.len() + 1];
+    let mut undamaged = vec![T::ZERO; r.data.len() + 1];
     // How many consecutive damaged items are there leading up to `i`?
     let mut consecutive: Vec<usize> = vec![0; r.data.len() + 1];
 
     // Let's populate the base case, with zero damaged segments
     // There is exactly 1 way to arrange the empty input
-    undamaged[0] = 1;
+    undamaged[0] = T::ONE;
     // That one arrangement is valid up until we hit a damaged cell.
     for (d, &ch) in (1..=r.data.len()).zip(r.data.iter()) {
         if ch != b'#' {
@@ -179,42 +390,180 @@ fn count_arrangements(r: &Record) -> usize {
         }
     }
 
-    let mut prev_undamaged = vec![0; r.data.len() + 1];
+    let mut prev_undamaged = vec![T::ZERO; r.data.len() + 1];
     for &count in &r.counts {
         std::mem::swap(&mut undamaged, &mut prev_undamaged);
-        undamaged[0] = 0;
+        undamaged[0] = T::ZERO;
         for d in 1..=r.data.len() {
             // This cell could be undamaged, in which case we can extend any existing arrangement by one.
             undamaged[d] = if r.data[d - 1] != b'#' {
-                undamaged[d - 1] + damaged[d - 1]
+                undamaged[d - 1].add(damaged[d - 1])?
             } else {
-                0
+                T::ZERO
             };
             // This could be the end of a damaged segment, in which case we can only use _undamaged_ arragements
             damaged[d] = if consecutive[d] >= count {
                 prev_undamaged[d - count]
             } else {
-                0
+                T::ZERO
             };
         }
     }
-    undamaged[r.data.len()] + damaged[r.data.len()]
+    undamaged[r.data.len()].add(damaged[r.data.len()])
 }
 
-struct Record {
-    data: Vec<u8>,
-    counts: Vec<usize>,
+/// One row of the condition records: `data` is the springs (`.`, `#` or `?`), and
+/// `counts` is the sizes of the damaged groups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub data: Vec<u8>,
+    pub counts: Vec<usize>,
 }
-fn parse_input(input: &str) -> anyhow::Result<Vec<Record>> {
-    input
-        .trim()
-        .lines()
-        .map(|l| {
-            let (_, record) = all_consuming(record_parser)(l.trim())
-                .map_err(|err| anyhow!("could not parse {l}: {err}"))?;
-            Ok(record)
+impl Record {
+    /// `k` copies of the record, with the data joined by `?`.
+    pub fn unfold(&self, k: usize) -> Record {
+        let mut unfolded = Record {
+            data: Vec::new(),
+            counts: Vec::new(),
+        };
+        for i in 0..k {
+            if i > 0 {
+                unfolded.data.push(b'?');
+            }
+            unfolded.data.extend_from_slice(&self.data);
+            unfolded.counts.extend_from_slice(&self.counts);
+        }
+        unfolded
+    }
+}
+impl Record {
+    /// Every concrete arrangement of the record, as a string of `#` and `.`, in sorted order.
+    /// Fails if there are too many of them to count in a `u128`.
+    pub fn arrangements(&self) -> anyhow::Result<Arrangements> {
+        Arrangements::new(self.clone())
+    }
+}
+
+/// The arrangements of a record. Backed by a table of how many arrangements each
+/// suffix of the record has, so we can jump straight to the `n`th one without
+/// visiting the others. Counts are `u128`, so this is meant for folded records.
+pub struct Arrangements {
+    record: Record,
+    // ways[i][j] is the number of arrangements of (data[i..], counts[j..])
+    ways: Vec<Vec<u128>>,
+    next: u128,
+}
+impl Arrangements {
+    fn new(record: Record) -> anyhow::Result<Self> {
+        let overflow = || {
+            anyhow!(
+                "arrangement count for {} overflows u128",
+                String::from_utf8_lossy(&record.data)
+            )
+        };
+        let (d, c) = (record.data.len(), record.counts.len());
+        let mut ways: Vec<Vec<u128>> = vec![vec![0; c + 1]; d + 1];
+        ways[d][c] = 1;
+        for i in (0..d).rev() {
+            if record.data[i] != b'#' {
+                ways[i][c] = ways[i + 1][c];
+            }
+            for j in (0..c).rev() {
+                if record.data[i] != b'#' {
+                    ways[i][j] = ways[i][j]
+                        .checked_add(ways[i + 1][j])
+                        .ok_or_else(overflow)?;
+                }
+                if let Some(next) = Self::place(&record, i, j) {
+                    ways[i][j] = ways[i][j]
+                        .checked_add(ways[next][j + 1])
+                        .ok_or_else(overflow)?;
+                }
+            }
+        }
+        Ok(Arrangements {
+            record,
+            ways,
+            next: 0,
         })
-        .collect()
+    }
+
+    // If the `j`th damaged segment can start at `i`, where does the data pick up after it?
+    fn place(record: &Record, i: usize, j: usize) -> Option<usize> {
+        let count = record.counts[j];
+        let rest = &record.data[i..];
+        if rest.len() < count || rest[..count].contains(&b'.') {
+            return None;
+        }
+        match rest.get(count) {
+            None => Some(i + count),
+            Some(b'#') => None,
+            Some(_) => Some(i + count + 1),
+        }
+    }
+
+    pub fn total(&self) -> u128 {
+        self.ways[0][0]
+    }
+
+    /// The `n`th arrangement in sorted order (`#` sorts before `.`).
+    pub fn get(&self, mut n: u128) -> Option<String> {
+        if n >= self.total() {
+            return None;
+        }
+        let Record { data, counts } = &self.record;
+        let mut acc = String::with_capacity(data.len());
+        let (mut i, mut j) = (0, 0);
+        while i < data.len() {
+            if let Some(next) = (j < counts.len())
+                .then(|| Self::place(&self.record, i, j))
+                .flatten()
+            {
+                let w = self.ways[next][j + 1];
+                if n < w {
+                    acc.extend(std::iter::repeat_n('#', counts[j]));
+                    acc.extend(std::iter::repeat_n('.', next - i - counts[j]));
+                    (i, j) = (next, j + 1);
+                    continue;
+                }
+                n -= w;
+            }
+            // Every remaining arrangement has this cell undamaged.
+            acc.push('.');
+            i += 1;
+        }
+        Some(acc)
+    }
+
+    /// An arrangement chosen uniformly at random, or `None` if there aren't any.
+    pub fn sample(&self, rng: &mut impl Rng) -> Option<String> {
+        if self.total() == 0 {
+            return None;
+        }
+        self.get(rng.gen_range(0..self.total()))
+    }
+}
+impl Iterator for Arrangements {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        let result = self.get(self.next)?;
+        self.next += 1;
+        Some(result)
+    }
+}
+
+impl FromStr for Record {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (_, record) = all_consuming(record_parser)(s.trim())
+            .map_err(|err| anyhow!("could not parse {s}: {err}"))?;
+        Ok(record)
+    }
+}
+fn parse_input(input: &str) -> anyhow::Result<Vec<Record>> {
+    input.trim().lines().map(str::parse).collect()
 }
 
 fn record_parser(input: &str) -> IResult<&str, Record> {
@@ -261,6 +610,169 @@ mod test {
         );
     }
 
+    #[test]
+    fn strategies_sample_input() {
+        for strategy in Strategy::ALL {
+            assert_eq!(
+                part1_with(SAMPLE_INPUT, strategy).unwrap(),
+                21,
+                "{strategy:?}"
+            );
+        }
+        for strategy in [Strategy::Memoized, Strategy::Dp2d, Strategy::Dp1d] {
+            assert_eq!(
+                part2_with(SAMPLE_INPUT, strategy).unwrap(),
+                525152,
+                "{strategy:?}"
+            );
+        }
+        assert_eq!("dp2d".parse::<Strategy>().unwrap(), Strategy::Dp2d);
+        assert!("fastest".parse::<Strategy>().is_err());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn strategies_agree(
+            data in "[.#?]{0,16}",
+            counts in proptest::collection::vec(1..5usize, 0..5),
+        ) {
+            let r = Record { data: data.into_bytes(), counts };
+            let expected = count_recursive::<usize>(&r).unwrap();
+            for strategy in Strategy::ALL {
+                proptest::prop_assert_eq!(strategy.count_arrangements(&r).unwrap(), expected, "{:?}", strategy);
+            }
+        }
+    }
+
+    #[test]
+    fn unfolded_matches_part2() {
+        let records = parse_input(SAMPLE_INPUT).unwrap();
+        let expected = [1, 16384, 1, 16, 2500, 506250];
+        for (r, e) in records.iter().zip(expected) {
+            assert_eq!(count_arrangements_unfolded(r, 5).unwrap(), e);
+        }
+        assert_eq!(part2_unfolded(SAMPLE_INPUT, 5).unwrap(), 525152);
+        assert_eq!(part2_unfolded(SAMPLE_INPUT, 1).unwrap(), 21);
+    }
+
+    #[test]
+    fn unfolded_large_factors() {
+        let r: Record = "???.### 1,1,3".parse().unwrap();
+        assert_eq!(count_arrangements_unfolded(&r, 0).unwrap(), 1);
+        assert_eq!(count_arrangements_unfolded(&r, MAX_UNFOLD).unwrap(), 1);
+        let err = count_arrangements_unfolded(&r, 1 << 40).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't unfold 1099511627776 times, the most is 1000"
+        );
+
+        // Each extra copy multiplies the count by 8 here, so u128 runs out around k = 43.
+        let r: Record = ".??..??...?##. 1,1,3".parse().unwrap();
+        assert_eq!(count_arrangements_unfolded(&r, 6).unwrap(), 16384 * 8);
+        assert_eq!(
+            count_arrangements_unfolded(&r, 42).unwrap(),
+            4 * 8u128.pow(41)
+        );
+        let err = count_arrangements_unfolded(&r, 50).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "arrangement count for .??..??...?##. unfolded 50 times overflows u128"
+        );
+    }
+
+    #[test]
+    fn arrangements_sample_input() {
+        let r: Record = "?###???????? 3,2,1".parse().unwrap();
+        assert_eq!(
+            r.arrangements().unwrap().collect::<Vec<_>>(),
+            vec![
+                ".###.##.#...",
+                ".###.##..#..",
+                ".###.##...#.",
+                ".###.##....#",
+                ".###..##.#..",
+                ".###..##..#.",
+                ".###..##...#",
+                ".###...##.#.",
+                ".###...##..#",
+                ".###....##.#",
+            ]
+        );
+        let r: Record = "???.### 1,1,3".parse().unwrap();
+        assert_eq!(
+            r.arrangements().unwrap().collect::<Vec<_>>(),
+            vec!["#.#.###"]
+        );
+
+        let r: Record = ".??..??...?##. 1,1,3".parse().unwrap();
+        assert_eq!(
+            r.unfold(42).arrangements().unwrap().total(),
+            4 * 8u128.pow(41)
+        );
+        let err = r.unfold(50).arrangements().err().unwrap();
+        assert!(err.to_string().ends_with("overflows u128"), "{err}");
+    }
+
+    #[test]
+    fn sample_is_uniform_ish() {
+        use rand::SeedableRng;
+        let r: Record = "?###???????? 3,2,1".parse().unwrap();
+        let arrangements = r.arrangements().unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(12);
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for _ in 0..10_000 {
+            *seen
+                .entry(arrangements.sample(&mut rng).unwrap())
+                .or_default() += 1;
+        }
+        assert_eq!(seen.len(), 10);
+        assert!(seen.values().all(|&n| (800..1200).contains(&n)), "{seen:?}");
+
+        let impossible: Record = "### 1".parse().unwrap();
+        assert_eq!(impossible.arrangements().unwrap().sample(&mut rng), None);
+    }
+
+    // Recomputes the damaged group sizes of a concrete arrangement.
+    fn groups(arrangement: &str) -> Vec<usize> {
+        arrangement
+            .split('.')
+            .filter(|g| !g.is_empty())
+            .map(|g| g.len())
+            .collect()
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn arrangements_are_valid_and_complete(
+            data in "[.#?]{0,14}",
+            counts in proptest::collection::vec(1..5usize, 0..5),
+        ) {
+            let r = Record { data: data.into_bytes(), counts };
+            let all: Vec<String> = r.arrangements().unwrap().collect();
+            proptest::prop_assert_eq!(all.len(), count_recursive::<usize>(&r).unwrap());
+            proptest::prop_assert!(all.windows(2).all(|w| w[0] < w[1]));
+            for a in &all {
+                proptest::prop_assert_eq!(groups(a), r.counts.clone());
+                let fits = a.bytes().zip(&r.data).all(|(x, &y)| y == b'?' || x == y);
+                proptest::prop_assert!(fits, "{} does not fit {:?}", a, r);
+            }
+        }
+    }
+
+    #[test]
+    fn part2_overflow_is_an_error() {
+        let input = format!("{} {}", "?".repeat(30), ["1"; 8].join(","));
+        // Too slow to try with `Recursive`, but it would fail the same way.
+        for strategy in [Strategy::Memoized, Strategy::Dp2d, Strategy::Dp1d] {
+            let err = part2_with(&input, strategy).unwrap_err();
+            assert!(
+                err.to_string().ends_with("overflows usize"),
+                "{strategy:?}: {err}"
+            );
+        }
+        assert!(part2_unfolded(&input, 5).is_ok());
+    }
+
     #[test]
     fn part2_sample_input() {
         assert_eq!(part2(SAMPLE_INPUT).unwrap(), 525152);
@@ -1,6 +1,5 @@
 use crate::grid::Grid;
 use anyhow::anyhow;
-use itertools::iproduct;
 use nom::{
     bytes::complete::is_a,
     character::complete::{multispace1, newline, space0},
@@ -11,7 +10,7 @@ use nom::{
 };
 use std::{
     collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
+    hash::{BuildHasher, BuildHasherDefault},
 };
 
 /*
@@ -130,77 +129,170 @@ In each pattern, fix the smudge and find the different line of reflection. What
 
 pub fn part1(input: &str) -> anyhow::Result<i32> {
     let grids = parse_input(input)?;
-    Ok(grids
-        .iter()
-        .map(|grid| find_symmetries(grid).into_iter().sum::<i32>())
-        .sum())
+    Ok(grids.iter().map(|grid| summarize(grid, 0)).sum())
 }
 
 pub fn part2(input: &str) -> anyhow::Result<i32> {
     let grids = parse_input(input)?;
-    let mut total = 0;
-    for mut grid in grids {
-        let s0 = find_symmetries(&grid);
-        for (i, j) in iproduct!(0..grid.height(), 0..grid.width()) {
-            smudge(grid.get_mut(i, j).unwrap());
-            let s1 = find_symmetries(&grid);
-            if !s1.is_empty() && s1 != s0 {
-                total += s1.into_iter().filter(|v| !s0.contains(v)).sum::<i32>();
-                break;
-            }
-            smudge(grid.get_mut(i, j).unwrap());
+    Ok(grids.iter().map(|grid| summarize(grid, 1)).sum())
+}
+
+fn summarize(grid: &Grid<u8>, smudges: usize) -> i32 {
+    find_reflections(grid, smudges)
+        .iter()
+        .map(Reflection::summary)
+        .sum()
+}
+
+/// Which way a line of reflection runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// Between two rows.
+    Horizontal,
+    /// Between two columns.
+    Vertical,
+}
+
+/// A cell that doesn't match its reflection. Flipping either one fixes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Smudge {
+    pub cell: (i32, i32),
+    pub mirror: (i32, i32),
+}
+
+/// A line of reflection, and the smudges that have to be fixed for it to be exact.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reflection {
+    pub axis: Axis,
+    /// The number of rows above (or columns to the left of) the line.
+    pub before: i32,
+    pub smudges: Vec<Smudge>,
+}
+
+impl Reflection {
+    /// This reflection's contribution to the puzzle answer.
+    pub fn summary(&self) -> i32 {
+        match self.axis {
+            Axis::Horizontal => 100 * self.before,
+            Axis::Vertical => self.before,
         }
     }
-    Ok(total)
 }
 
-fn smudge(c: &mut u8) {
-    *c = if *c == b'#' { b'.' } else { b'#' };
+/// Every line of reflection that's off by exactly `smudges` cells, vertical ones first.
+pub fn find_reflections(grid: &Grid<u8>, smudges: usize) -> Vec<Reflection> {
+    find_reflections_with(
+        grid,
+        smudges,
+        &BuildHasherDefault::<DefaultHasher>::default(),
+    )
 }
 
-fn find_symmetries(grid: &Grid<u8>) -> Vec<i32> {
-    find_vertical_symmetry(grid)
-        .into_iter()
-        .chain(find_horizontal_symmetry(grid).into_iter().map(|v| 100 * v))
-        .collect()
+// Lines are only hashed to quickly rule out matches, so any `hasher` gives the same answer.
+fn find_reflections_with(
+    grid: &Grid<u8>,
+    smudges: usize,
+    hasher: &impl BuildHasher,
+) -> Vec<Reflection> {
+    let mut acc = Vec::new();
+    for axis in [Axis::Vertical, Axis::Horizontal] {
+        for (before, mismatches) in near_misses(&lines(grid, axis), smudges, hasher) {
+            let smudges = mismatches
+                .into_iter()
+                .map(|(a, b, idx)| match axis {
+                    Axis::Horizontal => Smudge {
+                        cell: (a, idx),
+                        mirror: (b, idx),
+                    },
+                    Axis::Vertical => Smudge {
+                        cell: (idx, a),
+                        mirror: (idx, b),
+                    },
+                })
+                .collect();
+            acc.push(Reflection {
+                axis,
+                before,
+                smudges,
+            });
+        }
+    }
+    acc
 }
-fn find_horizontal_symmetry(grid: &Grid<u8>) -> Vec<i32> {
-    let digests: Vec<u64> = (0..grid.height())
-        .map(|i| {
-            let mut h = DefaultHasher::new();
-            for j in 0..grid.width() {
-                grid.get(i, j).hash(&mut h);
-            }
-            h.finish()
-        })
-        .collect();
-    (1..grid.height())
-        .filter(|&i| {
-            (0..i)
-                .rev()
-                .zip(i..grid.height())
-                .all(|(a, b)| digests[a as usize] == digests[b as usize])
-        })
-        .collect()
+
+/// Parses the patterns and finds their reflections; see [`find_reflections`].
+pub fn reflections(input: &str, smudges: usize) -> anyhow::Result<Vec<Vec<Reflection>>> {
+    let grids = parse_input(input)?;
+    Ok(grids
+        .iter()
+        .map(|grid| find_reflections(grid, smudges))
+        .collect())
+}
+
+// The rows of the grid for a horizontal axis, or its columns for a vertical one.
+fn lines(grid: &Grid<u8>, axis: Axis) -> Vec<Vec<u8>> {
+    match axis {
+        Axis::Horizontal => grid.rows().clone(),
+        Axis::Vertical => (0..grid.width())
+            .map(|j| (0..grid.height()).map(|i| grid[(i, j)]).collect())
+            .collect(),
+    }
 }
-fn find_vertical_symmetry(grid: &Grid<u8>) -> Vec<i32> {
-    let digests: Vec<u64> = (0..grid.width())
-        .map(|j| {
-            let mut h = DefaultHasher::new();
-            for i in 0..grid.height() {
-                grid.get(i, j).hash(&mut h);
+
+// A cell that differs from its reflection, as (line, mirrored line, index within the line).
+type Mismatch = (i32, i32, i32);
+
+// Finds the places to fold `lines` so that exactly `k` cells differ from their reflections,
+// along with those cells. Gives up on a fold as soon as it's seen more than `k` differences.
+fn near_misses(
+    lines: &[Vec<u8>],
+    k: usize,
+    hasher: &impl BuildHasher,
+) -> Vec<(i32, Vec<Mismatch>)> {
+    let digests: Vec<u64> = lines.iter().map(|line| hasher.hash_one(line)).collect();
+    let exact: Vec<Exact> = lines.iter().map(|line| Exact::new(line)).collect();
+    let mut acc = Vec::new();
+    'fold: for fold in 1..lines.len() {
+        let mut mismatches = Vec::new();
+        for (a, b) in (0..fold).rev().zip(fold..lines.len()) {
+            // Different digests mean different lines, but equal ones could be a collision.
+            if digests[a] == digests[b] && exact[a] == exact[b] {
+                continue;
+            }
+            for (idx, (x, y)) in lines[a].iter().zip(&lines[b]).enumerate() {
+                if x != y {
+                    if mismatches.len() == k {
+                        continue 'fold;
+                    }
+                    mismatches.push((a as i32, b as i32, idx as i32));
+                }
             }
-            h.finish()
-        })
-        .collect();
-    (1..grid.width())
-        .filter(|&j| {
-            (0..j)
-                .rev()
-                .zip(j..grid.width())
-                .all(|(a, b)| digests[a as usize] == digests[b as usize])
-        })
-        .collect()
+        }
+        if mismatches.len() == k {
+            acc.push((fold as i32, mismatches));
+        }
+    }
+    acc
+}
+
+// A line's contents, in a form that's cheap to compare exactly.
+#[derive(PartialEq, Eq)]
+enum Exact<'a> {
+    // One bit per cell, set for '#'.
+    Packed(u128),
+    Cells(&'a [u8]),
+}
+
+impl<'a> Exact<'a> {
+    fn new(line: &'a [u8]) -> Self {
+        if line.len() > 128 || line.iter().any(|&c| c != b'#' && c != b'.') {
+            return Exact::Cells(line);
+        }
+        Exact::Packed(
+            line.iter()
+                .fold(0, |acc, &c| (acc << 1) | u128::from(c == b'#')),
+        )
+    }
 }
 
 fn parse_input(input: &str) -> anyhow::Result<Vec<Grid<u8>>> {
@@ -265,6 +357,125 @@ mod test {
         assert_eq!(part2(SAMPLE_INPUT).unwrap(), 400);
     }
 
+    #[test]
+    fn smudges_sample_input() {
+        let found = reflections(SAMPLE_INPUT, 1).unwrap();
+        assert_eq!(
+            found,
+            vec![
+                vec![Reflection {
+                    axis: Axis::Horizontal,
+                    before: 3,
+                    smudges: vec![Smudge {
+                        cell: (0, 0),
+                        mirror: (5, 0),
+                    }],
+                }],
+                vec![Reflection {
+                    axis: Axis::Horizontal,
+                    before: 1,
+                    smudges: vec![Smudge {
+                        cell: (0, 4),
+                        mirror: (1, 4),
+                    }],
+                }],
+            ]
+        );
+        // Fixing the smudge makes the reflection exact.
+        for (mut grid, found) in parse_input(SAMPLE_INPUT).unwrap().into_iter().zip(found) {
+            let smudge = found[0].smudges[0];
+            let cell = &mut grid[smudge.cell];
+            *cell = if *cell == b'#' { b'.' } else { b'#' };
+            assert!(find_reflections(&grid, 0).contains(&Reflection {
+                smudges: vec![],
+                ..found[0].clone()
+            }));
+        }
+    }
+
+    #[test]
+    fn more_smudges() {
+        let grids = parse_input(SAMPLE_INPUT).unwrap();
+        let two = find_reflections(&grids[0], 2);
+        assert!(!two.is_empty());
+        assert!(two.iter().all(|r| r.smudges.len() == 2));
+        // Every fold is off by some number of cells: at most 3 pairs of 9-cell rows, or 4
+        // pairs of 7-cell columns.
+        let all: usize = (0..=28).map(|k| find_reflections(&grids[0], k).len()).sum();
+        assert_eq!(all, 6 + 8);
+    }
+
+    // Every line collides with every other line.
+    #[derive(Default)]
+    struct Collider;
+    impl std::hash::Hasher for Collider {
+        fn finish(&self) -> u64 {
+            0
+        }
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    #[test]
+    fn digest_collisions_are_harmless() {
+        let input = std::fs::read_to_string("data/day13.input").unwrap();
+        let collider = BuildHasherDefault::<Collider>::default();
+        for grid in parse_input(SAMPLE_INPUT)
+            .unwrap()
+            .iter()
+            .chain(&parse_input(&input).unwrap())
+        {
+            for k in 0..3 {
+                assert_eq!(
+                    find_reflections_with(grid, k, &collider),
+                    find_reflections(grid, k),
+                    "{grid:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exact_matches_cells() {
+        let input = std::fs::read_to_string("data/day13.input").unwrap();
+        for grid in parse_input(&input).unwrap() {
+            for axis in [Axis::Vertical, Axis::Horizontal] {
+                let lines = lines(&grid, axis);
+                for a in &lines {
+                    for b in &lines {
+                        assert_eq!(Exact::new(a) == Exact::new(b), a == b, "{a:?} {b:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wide_patterns() {
+        // Too wide to pack into a u128, with a smudge in the last column.
+        let row: String = (0..200)
+            .map(|j| if j % 3 == 0 { '#' } else { '.' })
+            .collect();
+        let smudged: String = row[..199].to_string() + "#";
+        let grid = &parse_input(&[row.as_str(), &row, &smudged, &row].join("\n")).unwrap()[0];
+        let collider = BuildHasherDefault::<Collider>::default();
+        for hasher_collides in [false, true] {
+            // The rows are periodic, so there are plenty of vertical reflections too.
+            let found = |k| {
+                let found = match hasher_collides {
+                    true => find_reflections_with(grid, k, &collider),
+                    false => find_reflections(grid, k),
+                };
+                found
+                    .into_iter()
+                    .filter(|r| r.axis == Axis::Horizontal)
+                    .map(|r| (r.before, r.smudges.iter().map(|s| s.cell).collect()))
+                    .collect::<Vec<(i32, Vec<(i32, i32)>)>>()
+            };
+            assert_eq!(found(0), vec![(1, vec![])]);
+            assert_eq!(found(1), vec![(2, vec![(1, 199)]), (3, vec![(2, 199)])]);
+        }
+    }
+
     #[test]
     fn part2_real_input() {
         assert_eq!(
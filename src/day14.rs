@@ -101,8 +101,8 @@ In the above example, after 1000000000 cycles, the total load on the north suppo
 Run the spin cycle for 1000000000 cycles. Afterward, what is the total load on the north support beams?
 */
 
-use crate::grid::Grid;
-use anyhow::anyhow;
+use crate::{cycle, grid::Grid};
+use anyhow::{anyhow, bail};
 use nom::{
     branch::alt,
     bytes::complete::tag,
@@ -112,10 +112,7 @@ use nom::{
     sequence::delimited,
     IResult,
 };
-use std::{
-    collections::{hash_map::Entry, HashMap},
-    fmt::{Debug, Write},
-};
+use std::fmt::{Debug, Write};
 
 pub fn part1(input: &str) -> anyhow::Result<i32> {
     let mut grid = parse_input(input)?;
@@ -129,32 +126,50 @@ pub fn part1(input: &str) -> anyhow::Result<i32> {
 }
 
 pub fn part2(input: &str) -> anyhow::Result<i32> {
-    let mut grid = parse_input(input)?;
+    part2_with(input, Representation::Grid)
+}
 
-    let mut record: HashMap<Grid<Cell>, usize> = HashMap::new();
-    let mut idx = 0;
-    let cycle_length = loop {
-        match record.entry(grid.clone()) {
-            Entry::Occupied(prev) => break idx - prev.get(),
-            Entry::Vacant(slot) => slot.insert(idx),
-        };
-        tilt_cycle(&mut grid);
-        idx += 1;
-    };
+/// How the platform is stored while we spin it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    /// A `Grid<Cell>`, tilted one cell at a time.
+    Grid,
+    /// Per-row bitmasks, tilted one segment (the run between two anchors) at a time.
+    Bits,
+}
 
+pub fn part2_with(input: &str, repr: Representation) -> anyhow::Result<i32> {
+    let grid = parse_input(input)?;
     let target = 1_000_000_000;
-    idx += (target - idx) / cycle_length * cycle_length;
+    match repr {
+        Representation::Grid => {
+            let grid = cycle::state_at(grid, tilt_cycle, fingerprint, target);
+            let total = grid
+                .enumerate()
+                .filter(|(_pos, &cell)| cell == Cell::Rock)
+                .map(|((i, _j), _cell)| grid.height() - i)
+                .sum();
+            Ok(total)
+        }
+        Representation::Bits => {
+            let platform = Platform::new(&grid)?;
+            let platform =
+                cycle::state_at(platform, Platform::tilt_cycle, |p| p.rocks.clone(), target);
+            Ok(platform.load())
+        }
+    }
+}
 
-    while idx < target {
-        tilt_cycle(&mut grid);
-        idx += 1;
+// Anchors never move, so the positions of the rocks are enough to identify a grid.
+// Packing them into bits is much cheaper to hash and store than cloning the grid.
+fn fingerprint(grid: &Grid<Cell>) -> Vec<u64> {
+    let mut bits = vec![0; (grid.height() * grid.width()) as usize / 64 + 1];
+    for (idx, (_pos, &cell)) in grid.enumerate().enumerate() {
+        if cell == Cell::Rock {
+            bits[idx / 64] |= 1 << (idx % 64);
+        }
     }
-    let total = grid
-        .enumerate()
-        .filter(|(_pos, &cell)| cell == Cell::Rock)
-        .map(|((i, _j), _cell)| grid.height() - i)
-        .sum();
-    Ok(total)
+    bits
 }
 
 fn tilt_cycle(grid: &mut Grid<Cell>) {
@@ -239,6 +254,154 @@ fn tilt_right(grid: &mut Grid<Cell>) {
     }
 }
 
+const MAX_DIM: usize = 128;
+
+// The same platform, as bitmasks: bit `j` of `rocks[i]` is set iff there is a rock at `(i, j)`.
+// North/south tilts transpose the rocks so that every tilt is a row-wise operation.
+struct Platform {
+    height: usize,
+    rocks: Vec<u128>,
+    anchors: Anchors,
+}
+// The anchors never move, so we precompute the free segments between them once.
+struct Anchors {
+    rows: Vec<Vec<Segment>>,
+    cols: Vec<Vec<Segment>>,
+}
+struct Segment {
+    start: u32,
+    end: u32,
+    mask: u128,
+}
+impl Platform {
+    fn new(grid: &Grid<Cell>) -> anyhow::Result<Self> {
+        let (height, width) = (grid.height() as usize, grid.width() as usize);
+        if height > MAX_DIM || width > MAX_DIM {
+            bail!(
+                "{height}x{width} platform is too large, at most {MAX_DIM}x{MAX_DIM} is supported"
+            );
+        }
+        let mut rocks = vec![0; MAX_DIM];
+        let mut anchors = vec![0; MAX_DIM];
+        for ((i, j), &cell) in grid.enumerate() {
+            match cell {
+                Cell::Ground => {}
+                Cell::Anchor => anchors[i as usize] |= 1 << j,
+                Cell::Rock => rocks[i as usize] |= 1 << j,
+            }
+        }
+        let rows = anchors
+            .iter()
+            .take(height)
+            .map(|&a| segments(a, width))
+            .collect();
+        transpose(&mut anchors);
+        let cols = anchors
+            .iter()
+            .take(width)
+            .map(|&a| segments(a, height))
+            .collect();
+        Ok(Platform {
+            height,
+            rocks,
+            anchors: Anchors { rows, cols },
+        })
+    }
+
+    fn tilt_cycle(&mut self) {
+        let anchors = &self.anchors;
+        transpose(&mut self.rocks);
+        tilt_low(&mut self.rocks, &anchors.cols);
+        transpose(&mut self.rocks);
+        tilt_low(&mut self.rocks, &anchors.rows);
+        transpose(&mut self.rocks);
+        tilt_high(&mut self.rocks, &anchors.cols);
+        transpose(&mut self.rocks);
+        tilt_high(&mut self.rocks, &anchors.rows);
+    }
+
+    fn load(&self) -> i32 {
+        self.rocks
+            .iter()
+            .take(self.height)
+            .enumerate()
+            .map(|(i, row)| row.count_ones() as i32 * (self.height - i) as i32)
+            .sum()
+    }
+}
+
+fn segments(anchors: u128, len: usize) -> Vec<Segment> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    for end in 0..=len {
+        if end == len || anchors & (1 << end) != 0 {
+            if end > start {
+                result.push(Segment {
+                    start: start as u32,
+                    end: end as u32,
+                    mask: ones(end - start) << start,
+                });
+            }
+            start = end + 1;
+        }
+    }
+    result
+}
+
+fn ones(n: usize) -> u128 {
+    if n == 0 {
+        0
+    } else {
+        u128::MAX >> (128 - n)
+    }
+}
+
+// Slides every rock in every segment towards bit 0.
+fn tilt_low(lines: &mut [u128], segments: &[Vec<Segment>]) {
+    for (line, segs) in lines.iter_mut().zip(segments) {
+        let mut next = 0;
+        for seg in segs {
+            let n = (*line & seg.mask).count_ones() as usize;
+            next |= ones(n) << seg.start;
+        }
+        *line = next;
+    }
+}
+
+// Slides every rock in every segment towards the highest bit.
+fn tilt_high(lines: &mut [u128], segments: &[Vec<Segment>]) {
+    for (line, segs) in lines.iter_mut().zip(segments) {
+        let mut next = 0;
+        for seg in segs {
+            let n = (*line & seg.mask).count_ones();
+            // An empty segment ending at bit 128 would otherwise shift by the full width.
+            if n > 0 {
+                next |= ones(n as usize) << (seg.end - n);
+            }
+        }
+        *line = next;
+    }
+}
+
+// Transposes a 128x128 bit matrix in place by recursively swapping off-diagonal blocks
+// (see Hacker's Delight, section 7-3).
+fn transpose(m: &mut [u128]) {
+    debug_assert_eq!(m.len(), MAX_DIM);
+    let mut j = 64;
+    let mut mask: u128 = u64::MAX as u128;
+    while j != 0 {
+        let mut k = 0;
+        while k < MAX_DIM {
+            let t = ((m[k] >> j) ^ m[k + j]) & mask;
+            m[k] ^= t << j;
+            m[k + j] ^= t;
+            k = (k + j + 1) & !j;
+        }
+        j >>= 1;
+        mask ^= mask << j;
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Hash)]
 enum Cell {
     Ground,
@@ -315,6 +478,66 @@ mod test {
         assert_eq!(part2(SAMPLE_INPUT).unwrap(), 64);
     }
 
+    #[test]
+    fn part2_real_input_bits() {
+        assert_eq!(
+            part2_with(
+                &std::fs::read_to_string("data/day14.input").unwrap(),
+                Representation::Bits
+            )
+            .unwrap(),
+            95254,
+        );
+    }
+
+    #[test]
+    fn transpose_smoke_test() {
+        let mut m: Vec<u128> = (0..MAX_DIM as u128).map(|i| i * i + 7 * i).collect();
+        let orig = m.clone();
+        transpose(&mut m);
+        for (i, j) in itertools::iproduct!(0..MAX_DIM, 0..MAX_DIM) {
+            assert_eq!((orig[i] >> j) & 1, (m[j] >> i) & 1);
+        }
+    }
+
+    #[test]
+    fn bits_match_grid() {
+        let input = std::fs::read_to_string("data/day14.input").unwrap();
+        let mut grid = parse_input(&input).unwrap();
+        let mut platform = Platform::new(&grid).unwrap();
+        for _ in 0..10 {
+            tilt_cycle(&mut grid);
+            platform.tilt_cycle();
+            assert_eq!(platform.rocks, Platform::new(&grid).unwrap().rocks);
+        }
+    }
+
+    #[test]
+    fn full_width_platform() {
+        // Segments that end at bit 128, both empty and not.
+        let input = [
+            ".".repeat(MAX_DIM),
+            "O".to_string() + &".".repeat(MAX_DIM - 2) + "#",
+        ]
+        .join("\n");
+        let mut grid = parse_input(&input).unwrap();
+        let mut platform = Platform::new(&grid).unwrap();
+        for _ in 0..3 {
+            tilt_cycle(&mut grid);
+            platform.tilt_cycle();
+            assert_eq!(platform.rocks, Platform::new(&grid).unwrap().rocks);
+        }
+        assert_eq!(
+            part2_with(&input, Representation::Bits).unwrap(),
+            part2_with(&input, Representation::Grid).unwrap(),
+        );
+    }
+
+    #[test]
+    fn part2_sample_input_bits() {
+        assert_eq!(part2_with(SAMPLE_INPUT, Representation::Bits).unwrap(), 64);
+    }
+
     #[test]
     fn part2_real_input() {
         assert_eq!(
@@ -1,3 +1,5 @@
+use std::{collections::HashMap, fmt};
+
 use anyhow::anyhow;
 use itertools::Itertools;
 use nom::{
@@ -167,45 +169,269 @@ pub fn part1(input: &str) -> anyhow::Result<usize> {
 
 pub fn part2(input: &str) -> anyhow::Result<usize> {
     let commands = parse_input(input)?;
-    let mut boxes: Vec<Box> = vec![Box::default(); 256];
-    for cmd in commands {
+    let mut lenses = LensMap::new();
+    for cmd in &commands {
+        lenses.apply(cmd);
+    }
+    Ok(lenses.focusing_power())
+}
+
+/// The HASHMAP: labelled lenses, sorted into boxes by the hash of their label. Within a box,
+/// lenses stay in the order they were first inserted.
+#[derive(Debug, Clone)]
+pub struct LensMap {
+    hash: fn(&str) -> usize,
+    boxes: Vec<LensBox>,
+}
+
+/// A lens, and where it sits in a [`LensMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lens<'a> {
+    pub label: &'a str,
+    pub focal_length: usize,
+    pub box_number: usize,
+    /// Starting from 0 at the front of the box.
+    pub slot: usize,
+}
+
+impl Lens<'_> {
+    pub fn focusing_power(&self) -> usize {
+        (1 + self.box_number) * (1 + self.slot) * self.focal_length
+    }
+}
+
+/// Shows how the lens's focusing power is calculated, the same way as the puzzle, e.g.
+/// `ab: 4 (box 3) * 2 (second slot) * 5 (focal length) = 40`.
+impl fmt::Display for Lens<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} (box {}) * {} ({} slot) * {} (focal length) = {}",
+            self.label,
+            1 + self.box_number,
+            self.box_number,
+            1 + self.slot,
+            ordinal(1 + self.slot),
+            self.focal_length,
+            self.focusing_power()
+        )
+    }
+}
+
+fn ordinal(n: usize) -> String {
+    const WORDS: [&str; 10] = [
+        "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth",
+        "tenth",
+    ];
+    if (1..=WORDS.len()).contains(&n) {
+        return WORDS[n - 1].to_owned();
+    }
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+/// One step of the initialization sequence, and the contents of the boxes right after it.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub command: Command,
+    /// The non-empty boxes, by box number, each with its `(label, focal length)`s in order.
+    pub boxes: Vec<(usize, Vec<(String, usize)>)>,
+}
+
+impl Step {
+    /// Every lens, in the order light passes through them.
+    pub fn lenses(&self) -> impl Iterator<Item = Lens<'_>> {
+        self.boxes.iter().flat_map(|(box_number, lenses)| {
+            lenses
+                .iter()
+                .enumerate()
+                .map(move |(slot, (label, focal_length))| Lens {
+                    label,
+                    focal_length: *focal_length,
+                    box_number: *box_number,
+                    slot,
+                })
+        })
+    }
+}
+
+/// Shows the step the same way as the puzzle's walkthrough.
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "After \"{}\":", self.command)?;
+        write_boxes(f, self.lenses())
+    }
+}
+
+/// Runs the initialization sequence, yielding the state after each step.
+pub fn trace(input: &str) -> anyhow::Result<impl Iterator<Item = Step>> {
+    let commands = parse_input(input)?;
+    let mut lenses = LensMap::new();
+    Ok(commands.into_iter().map(move |command| {
+        lenses.apply(&command);
+        // Only copy out the boxes that have something in them, not the whole map.
+        let boxes = lenses
+            .lenses()
+            .group_by(|lens| lens.box_number)
+            .into_iter()
+            .map(|(box_number, lenses)| {
+                let lenses = lenses
+                    .map(|lens| (lens.label.to_owned(), lens.focal_length))
+                    .collect();
+                (box_number, lenses)
+            })
+            .collect();
+        Step { command, boxes }
+    }))
+}
+
+impl LensMap {
+    /// The puzzle's configuration: 256 boxes, indexed with the HASH algorithm.
+    pub fn new() -> Self {
+        Self::with_hasher(256, my_hash)
+    }
+
+    /// Uses `hash(label) % boxes` to pick the box for each label.
+    pub fn with_hasher(boxes: usize, hash: fn(&str) -> usize) -> Self {
+        assert!(boxes > 0, "a LensMap needs at least one box");
+        LensMap {
+            hash,
+            boxes: vec![LensBox::default(); boxes],
+        }
+    }
+
+    pub fn apply(&mut self, cmd: &Command) {
         match cmd {
             Command::Set(label, value) => {
-                let b = &mut boxes[my_hash(&label)];
-                if let Some(slot) = b.lens.iter_mut().find(|(l, _v)| *l == label) {
-                    slot.1 = value;
-                } else {
-                    b.lens.push((label, value));
-                }
+                self.insert(label, *value);
             }
             Command::Del(label) => {
-                let b = &mut boxes[my_hash(&label)];
-                if let Some((idx, _)) = b.lens.iter().find_position(|(l, _v)| *l == label) {
-                    b.lens.remove(idx);
-                }
+                self.remove(label);
             }
         }
     }
-    let focusing_power = boxes
-        .iter()
-        .enumerate()
-        .map(|(i, b)| {
-            b.lens
-                .iter()
+
+    /// Replaces the focal length of the lens with this label, or adds a new lens at the back
+    /// of its box. Returns the old focal length, if any.
+    pub fn insert(&mut self, label: &str, focal_length: usize) -> Option<usize> {
+        let idx = self.box_number(label);
+        self.boxes[idx].insert(label, focal_length)
+    }
+
+    /// Removes the lens with this label, returning its focal length.
+    pub fn remove(&mut self, label: &str) -> Option<usize> {
+        let idx = self.box_number(label);
+        self.boxes[idx].remove(label)
+    }
+
+    pub fn get(&self, label: &str) -> Option<usize> {
+        let b = &self.boxes[self.box_number(label)];
+        let &slot = b.index.get(label)?;
+        b.slots[slot].as_ref().map(|(_, v)| *v)
+    }
+
+    pub fn len(&self) -> usize {
+        self.boxes.iter().map(|b| b.index.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.boxes.iter().all(|b| b.index.is_empty())
+    }
+
+    /// Every lens, in the order light passes through them.
+    pub fn lenses(&self) -> impl Iterator<Item = Lens<'_>> {
+        self.boxes.iter().enumerate().flat_map(|(box_number, b)| {
+            b.lenses()
                 .enumerate()
-                .map(|(j, (_label, value))| (1 + i) * (1 + j) * value)
-                .sum::<usize>()
+                .map(move |(slot, (label, focal_length))| Lens {
+                    label,
+                    focal_length,
+                    box_number,
+                    slot,
+                })
         })
-        .sum::<usize>();
-    Ok(focusing_power)
+    }
+
+    pub fn focusing_power(&self) -> usize {
+        self.lenses().map(|lens| lens.focusing_power()).sum()
+    }
+
+    fn box_number(&self, label: &str) -> usize {
+        (self.hash)(label) % self.boxes.len()
+    }
 }
 
-#[derive(Default, Clone)]
-struct Box {
-    lens: Vec<(String, usize)>,
+impl Default for LensMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lists the non-empty boxes the same way as the puzzle, e.g. `Box 0: [rn 1] [cm 2]`.
+impl fmt::Display for LensMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_boxes(f, self.lenses())
+    }
+}
+
+// `lenses` should be in order, as from `LensMap::lenses`.
+fn write_boxes<'a>(
+    f: &mut fmt::Formatter<'_>,
+    lenses: impl Iterator<Item = Lens<'a>>,
+) -> fmt::Result {
+    for (box_number, lenses) in &lenses.group_by(|lens| lens.box_number) {
+        write!(f, "Box {box_number}:")?;
+        for lens in lenses {
+            write!(f, " [{} {}]", lens.label, lens.focal_length)?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone)]
+struct LensBox {
+    // Removing a lens leaves a hole, so the other lenses don't have to shift and `index` stays
+    // valid. Once the holes outnumber the lenses, they're compacted away.
+    slots: Vec<Option<(String, usize)>>,
+    index: HashMap<String, usize>,
+}
+
+impl LensBox {
+    fn insert(&mut self, label: &str, focal_length: usize) -> Option<usize> {
+        if let Some(&slot) = self.index.get(label) {
+            let (_, old) = self.slots[slot].as_mut().unwrap();
+            return Some(std::mem::replace(old, focal_length));
+        }
+        self.index.insert(label.to_owned(), self.slots.len());
+        self.slots.push(Some((label.to_owned(), focal_length)));
+        None
+    }
+
+    fn remove(&mut self, label: &str) -> Option<usize> {
+        let slot = self.index.remove(label)?;
+        let (_, old) = self.slots[slot].take().unwrap();
+        if self.slots.len() > 2 * self.index.len() {
+            self.slots.retain(Option::is_some);
+            for (slot, (label, _)) in self.slots.iter().flatten().enumerate() {
+                *self.index.get_mut(label).unwrap() = slot;
+            }
+        }
+        Some(old)
+    }
+
+    fn lenses(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.slots.iter().flatten().map(|(l, v)| (l.as_str(), *v))
+    }
 }
 
-fn my_hash(w: &str) -> usize {
+/// The HASH algorithm.
+pub fn my_hash(w: &str) -> usize {
     let mut acc: usize = 0;
     for &b in w.as_bytes() {
         acc = (17 * (acc + b as usize)) % 256;
@@ -213,10 +439,20 @@ fn my_hash(w: &str) -> usize {
     acc
 }
 
-enum Command {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
     Set(String, usize),
     Del(String),
 }
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Set(label, value) => write!(f, "{label}={value}"),
+            Command::Del(label) => write!(f, "{label}-"),
+        }
+    }
+}
+
 fn parse_input(input: &str) -> anyhow::Result<Vec<Command>> {
     let input = input.trim();
     let (_, commands) = all_consuming(separated_list1(tag(","), command_parser))(input)
@@ -269,6 +505,135 @@ mod test {
         assert_eq!(part2(SAMPLE_INPUT).unwrap(), 145);
     }
 
+    #[test]
+    fn lens_map_display() {
+        let mut lenses = LensMap::new();
+        for cmd in &parse_input(SAMPLE_INPUT).unwrap() {
+            lenses.apply(cmd);
+        }
+        assert_eq!(
+            lenses.to_string(),
+            "Box 0: [rn 1] [cm 2]\nBox 3: [ot 7] [ab 5] [pc 6]\n"
+        );
+        assert_eq!(lenses.len(), 5);
+        assert_eq!(lenses.get("ot"), Some(7));
+        assert_eq!(lenses.get("qp"), None);
+        assert_eq!(
+            lenses
+                .lenses()
+                .map(|lens| lens.focusing_power())
+                .collect::<Vec<_>>(),
+            vec![1, 4, 28, 40, 72]
+        );
+    }
+
+    #[test]
+    fn lens_map_matches_naive_boxes() {
+        // Few boxes, so removals and compaction happen a lot.
+        let mut lenses = LensMap::with_hasher(3, my_hash);
+        let mut naive: Vec<Vec<(String, usize)>> = vec![vec![]; 3];
+        let labels = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        for step in 0..1000usize {
+            let label = labels[(step * 7 + step / 5) % labels.len()];
+            let b = &mut naive[my_hash(label) % 3];
+            let pos = b.iter().position(|(l, _)| l == label);
+            if step % 3 == 0 {
+                assert_eq!(lenses.remove(label), pos.map(|idx| b.remove(idx).1));
+            } else {
+                let old = match pos {
+                    Some(idx) => Some(std::mem::replace(&mut b[idx].1, step)),
+                    None => {
+                        b.push((label.to_owned(), step));
+                        None
+                    }
+                };
+                assert_eq!(lenses.insert(label, step), old);
+            }
+            let expected: Vec<(usize, &str, usize)> = naive
+                .iter()
+                .enumerate()
+                .flat_map(|(i, b)| b.iter().map(move |(l, v)| (i, l.as_str(), *v)))
+                .collect();
+            let actual: Vec<(usize, &str, usize)> = lenses
+                .lenses()
+                .map(|lens| (lens.box_number, lens.label, lens.focal_length))
+                .collect();
+            assert_eq!(actual, expected, "step {step}");
+        }
+    }
+
+    #[test]
+    fn trace_sample_input() {
+        let steps: Vec<String> = trace(SAMPLE_INPUT)
+            .unwrap()
+            .map(|step| step.to_string())
+            .collect();
+        assert_eq!(
+            steps.join("\n"),
+            r#"After "rn=1":
+Box 0: [rn 1]
+
+After "cm-":
+Box 0: [rn 1]
+
+After "qp=3":
+Box 0: [rn 1]
+Box 1: [qp 3]
+
+After "cm=2":
+Box 0: [rn 1] [cm 2]
+Box 1: [qp 3]
+
+After "qp-":
+Box 0: [rn 1] [cm 2]
+
+After "pc=4":
+Box 0: [rn 1] [cm 2]
+Box 3: [pc 4]
+
+After "ot=9":
+Box 0: [rn 1] [cm 2]
+Box 3: [pc 4] [ot 9]
+
+After "ab=5":
+Box 0: [rn 1] [cm 2]
+Box 3: [pc 4] [ot 9] [ab 5]
+
+After "pc-":
+Box 0: [rn 1] [cm 2]
+Box 3: [ot 9] [ab 5]
+
+After "pc=6":
+Box 0: [rn 1] [cm 2]
+Box 3: [ot 9] [ab 5] [pc 6]
+
+After "ot=7":
+Box 0: [rn 1] [cm 2]
+Box 3: [ot 7] [ab 5] [pc 6]
+"#
+        );
+    }
+
+    #[test]
+    fn focusing_power_breakdown() {
+        let last = trace(SAMPLE_INPUT).unwrap().last().unwrap();
+        let breakdown: Vec<String> = last.lenses().map(|l| l.to_string()).collect();
+        assert_eq!(
+            breakdown,
+            vec![
+                "rn: 1 (box 0) * 1 (first slot) * 1 (focal length) = 1",
+                "cm: 1 (box 0) * 2 (second slot) * 2 (focal length) = 4",
+                "ot: 4 (box 3) * 1 (first slot) * 7 (focal length) = 28",
+                "ab: 4 (box 3) * 2 (second slot) * 5 (focal length) = 40",
+                "pc: 4 (box 3) * 3 (third slot) * 6 (focal length) = 72",
+            ]
+        );
+        assert_eq!(
+            [11, 12, 21, 22, 23, 101, 111].map(ordinal),
+            ["11th", "12th", "21st", "22nd", "23rd", "101st", "111th"]
+        );
+    }
+
     #[test]
     fn part2_real_input() {
         assert_eq!(
@@ -104,64 +104,371 @@ use nom::{
     sequence::delimited,
     IResult,
 };
-use std::fmt::{Debug, Write};
+use std::{
+    fmt::{Debug, Write},
+    thread,
+};
 pub fn part1(input: &str) -> anyhow::Result<usize> {
-    let grid = parse_input(input)?;
-    Ok(count_energized(&grid, (Position(0, 0), Direction::Right)))
+    part1_with(input, Strategy::Worklist)
 }
 
 pub fn part2(input: &str) -> anyhow::Result<usize> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    part2_with(input, Strategy::Segments, threads)
+}
+
+/// How to follow the beam through the contraption.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Depth-first search on the call stack. Fast, but deep enough to overflow the stack on
+    /// large grids.
+    Recursive,
+    /// Depth-first search with an explicit stack.
+    Worklist,
+    /// Precomputes the path from each splitter to the next one, so that every entry point
+    /// reuses them rather than walking the same tiles again.
+    Segments,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 3] = [Strategy::Recursive, Strategy::Worklist, Strategy::Segments];
+}
+
+pub fn part1_with(input: &str, strategy: Strategy) -> anyhow::Result<usize> {
     let grid = parse_input(input)?;
-    let up = (0..grid.width())
-        .map(|j| count_energized(&grid, (Position(grid.height() - 1, j), Direction::Up)));
-    let down = (0..grid.width()).map(|j| count_energized(&grid, (Position(0, j), Direction::Down)));
-    let right =
-        (0..grid.height()).map(|i| count_energized(&grid, (Position(i, 0), Direction::Right)));
-    let left = (0..grid.height())
-        .map(|i| count_energized(&grid, (Position(i, grid.width() - 1), Direction::Left)));
-    Ok(up.chain(down).chain(left).chain(right).max().unwrap_or(0))
+    Ok(Counter::new(&grid, strategy).count((Position(0, 0), Direction::Right)))
+}
+
+/// Tries every entry point, splitting them between `threads` threads.
+pub fn part2_with(input: &str, strategy: Strategy, threads: usize) -> anyhow::Result<usize> {
+    let grid = parse_input(input)?;
+    let counter = Counter::new(&grid, strategy);
+    let entries = entries(&grid);
+    let chunk_size = entries.len().div_ceil(threads.max(1)).max(1);
+    let best = thread::scope(|scope| {
+        let workers: Vec<_> = entries
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let counter = &counter;
+                scope.spawn(move || chunk.iter().map(|&entry| counter.count(entry)).max())
+            })
+            .collect();
+        workers
+            .into_iter()
+            .filter_map(|worker| worker.join().unwrap())
+            .max()
+    });
+    Ok(best.unwrap_or(0))
+}
+
+// Every tile on the edge, heading away from that edge.
+fn entries(grid: &Grid<Cell>) -> Vec<(Position, Direction)> {
+    let (height, width) = (grid.height(), grid.width());
+    let up = (0..width).map(|j| (Position(height - 1, j), Direction::Up));
+    let down = (0..width).map(|j| (Position(0, j), Direction::Down));
+    let right = (0..height).map(|i| (Position(i, 0), Direction::Right));
+    let left = (0..height).map(|i| (Position(i, width - 1), Direction::Left));
+    up.chain(down).chain(left).chain(right).collect()
+}
+
+// Where the beam goes after passing through `cell` heading in `dir`. Splitters may send it
+// two ways.
+fn next_dirs(cell: Cell, dir: Direction) -> (Direction, Option<Direction>) {
+    match (cell, dir) {
+        (Cell::Ground, _) => (dir, None),
+        (Cell::MirrorUp /* aka '/' */, Direction::Up) => (Direction::Right, None),
+        (Cell::MirrorUp, Direction::Down) => (Direction::Left, None),
+        (Cell::MirrorUp, Direction::Left) => (Direction::Down, None),
+        (Cell::MirrorUp, Direction::Right) => (Direction::Up, None),
+        (Cell::MirrorDown /* aka '\' */, Direction::Up) => (Direction::Left, None),
+        (Cell::MirrorDown, Direction::Down) => (Direction::Right, None),
+        (Cell::MirrorDown, Direction::Left) => (Direction::Up, None),
+        (Cell::MirrorDown, Direction::Right) => (Direction::Down, None),
+        (Cell::SplitHoriz, Direction::Up | Direction::Down) => {
+            (Direction::Left, Some(Direction::Right))
+        }
+        (Cell::SplitVert, Direction::Left | Direction::Right) => {
+            (Direction::Up, Some(Direction::Down))
+        }
+        (Cell::SplitHoriz | Cell::SplitVert, _) => (dir, None),
+    }
+}
+
+// Counts energized tiles, with whatever precomputation the strategy needs.
+enum Counter<'a> {
+    Recursive(&'a Grid<Cell>),
+    Worklist(&'a Grid<Cell>),
+    Segments(Segments<'a>),
+}
+
+impl<'a> Counter<'a> {
+    fn new(grid: &'a Grid<Cell>, strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::Recursive => Counter::Recursive(grid),
+            Strategy::Worklist => Counter::Worklist(grid),
+            Strategy::Segments => Counter::Segments(Segments::new(grid)),
+        }
+    }
+
+    fn count(&self, entry: (Position, Direction)) -> usize {
+        match self {
+            Counter::Recursive(grid) => count_energized_recursive(grid, entry),
+            Counter::Worklist(grid) => count_energized(grid, entry),
+            Counter::Segments(segments) => segments.count_energized(entry),
+        }
+    }
+}
+
+fn count_energized(grid: &Grid<Cell>, entry: (Position, Direction)) -> usize {
+    propagate(grid, entry).count_positions()
+}
+
+// Every tile the beam passes through, along with the directions it's heading.
+fn propagate(grid: &Grid<Cell>, entry: (Position, Direction)) -> PosDirSet {
+    let mut vis: PosDirSet = PosDirSet::new(grid.size());
+    // The stack lives outside the loop, and `next_dirs` doesn't allocate, so this doesn't
+    // churn through lots of tiny vectors.
+    let mut stack = vec![entry];
+    while let Some((pos, dir)) = stack.pop() {
+        let Position(i, j) = pos;
+        let Some(&ch) = grid.get(i, j) else { continue };
+        if !vis.insert(pos, dir) {
+            continue;
+        }
+        let (first, second) = next_dirs(ch, dir);
+        stack.push((pos.step(first), first));
+        if let Some(second) = second {
+            stack.push((pos.step(second), second));
+        }
+    }
+    vis
 }
 
-fn count_energized(grid: &Grid<Cell>, (pos, dir): (Position, Direction)) -> usize {
+fn count_energized_recursive(grid: &Grid<Cell>, (pos, dir): (Position, Direction)) -> usize {
     fn explore(grid: &Grid<Cell>, vis: &mut PosDirSet, pos: Position, dir: Direction) {
         let Position(i, j) = pos;
         let Some(&ch) = grid.get(i, j) else { return };
         if !vis.insert(pos, dir) {
             return;
         }
-        match ch {
-            Cell::Ground => explore(grid, vis, pos.step(dir), dir),
-            Cell::MirrorUp /* aka '/' */ => match dir {
-                Direction::Up => explore(grid, vis, pos.step(Direction::Right), Direction::Right),
-                Direction::Down => explore(grid, vis, pos.step(Direction::Left), Direction::Left),
-                Direction::Left => explore(grid, vis, pos.step(Direction::Down), Direction::Down),
-                Direction::Right => explore(grid, vis, pos.step(Direction::Up), Direction::Up),
-            },
-            Cell::MirrorDown /* aka '\' */ => match dir {
-                Direction::Up => explore(grid, vis, pos.step(Direction::Left), Direction::Left),
-                Direction::Down => explore(grid, vis, pos.step(Direction::Right), Direction::Right),
-                Direction::Left => explore(grid, vis, pos.step(Direction::Up), Direction::Up),
-                Direction::Right => explore(grid, vis, pos.step(Direction::Down), Direction::Down),
-            },
-            Cell::SplitHoriz => match dir {
-                Direction::Up | Direction::Down => {
-                    explore(grid, vis, pos.step(Direction::Left), Direction::Left);
-                    explore(grid, vis, pos.step(Direction::Right), Direction::Right);
+        let (first, second) = next_dirs(ch, dir);
+        explore(grid, vis, pos.step(first), first);
+        if let Some(second) = second {
+            explore(grid, vis, pos.step(second), second);
+        }
+    }
+    let mut vis: PosDirSet = PosDirSet::new(grid.size());
+    explore(grid, &mut vis, pos, dir);
+    vis.count_positions()
+}
+
+// The beam's path between splitters. Every splitter sends the beam out the same two ways
+// (left and right, or up and down) no matter how it came in, so there are exactly two
+// segments leaving each splitter, and they're shared by every entry point.
+struct Segments<'a> {
+    grid: &'a Grid<Cell>,
+    // Indexed by tile; the position of each splitter in `outgoing`.
+    splitter_ids: Vec<Option<usize>>,
+    // The two segments leaving each splitter.
+    outgoing: Vec<[Segment; 2]>,
+}
+
+struct Segment {
+    // Tile indices, including the splitter at the end (if any).
+    tiles: Vec<usize>,
+    // The splitter the segment runs into, and which of its outgoing segments the beam
+    // continues along: both if the beam hits it side-on, otherwise just one.
+    end: Option<(usize, [bool; 2])>,
+}
+
+impl<'a> Segments<'a> {
+    fn new(grid: &'a Grid<Cell>) -> Self {
+        let mut splitter_ids = vec![None; (grid.height() * grid.width()) as usize];
+        let mut splitters = Vec::new();
+        for ((i, j), &cell) in grid.enumerate() {
+            if matches!(cell, Cell::SplitHoriz | Cell::SplitVert) {
+                splitter_ids[(i * grid.width() + j) as usize] = Some(splitters.len());
+                splitters.push((Position(i, j), cell));
+            }
+        }
+        let mut segments = Segments {
+            grid,
+            splitter_ids,
+            outgoing: Vec::with_capacity(splitters.len()),
+        };
+        for (pos, cell) in splitters {
+            let (a, b) = Self::exits(cell);
+            let outgoing = [a, b].map(|dir| segments.trace((pos.step(dir), dir)));
+            segments.outgoing.push(outgoing);
+        }
+        segments
+    }
+
+    // The two ways out of a splitter.
+    fn exits(cell: Cell) -> (Direction, Direction) {
+        match cell {
+            Cell::SplitHoriz => (Direction::Left, Direction::Right),
+            _ => (Direction::Up, Direction::Down),
+        }
+    }
+
+    // Follows the beam from `start` until it leaves the grid or reaches a splitter.
+    fn trace(&self, (mut pos, mut dir): (Position, Direction)) -> Segment {
+        let width = self.grid.width();
+        let mut tiles = Vec::new();
+        while let Some(&cell) = self.grid.get(pos.0, pos.1) {
+            let idx = (pos.0 * width + pos.1) as usize;
+            tiles.push(idx);
+            if let Some(id) = self.splitter_ids[idx] {
+                let (a, b) = Self::exits(cell);
+                let (first, second) = next_dirs(cell, dir);
+                let continues = [a, b].map(|exit| exit == first || Some(exit) == second);
+                return Segment {
+                    tiles,
+                    end: Some((id, continues)),
+                };
+            }
+            // Mirrors are reversible, so a segment can't loop without passing back through
+            // the splitter it started from.
+            (dir, _) = next_dirs(cell, dir);
+            pos = pos.step(dir);
+        }
+        Segment { tiles, end: None }
+    }
+
+    fn count_energized(&self, entry: (Position, Direction)) -> usize {
+        let mut energized = vec![false; self.splitter_ids.len()];
+        let mut count = 0;
+        let mut visited = vec![[false; 2]; self.outgoing.len()];
+        let first = self.trace(entry);
+        let mut stack = vec![&first];
+        while let Some(segment) = stack.pop() {
+            for &tile in &segment.tiles {
+                if !energized[tile] {
+                    energized[tile] = true;
+                    count += 1;
                 }
-                Direction::Left | Direction::Right => explore(grid, vis, pos.step(dir), dir),
             }
-            Cell::SplitVert => match dir {
-                Direction::Left | Direction::Right => {
-                    explore(grid, vis, pos.step(Direction::Up), Direction::Up);
-                    explore(grid, vis, pos.step(Direction::Down), Direction::Down);
+            let Some((id, continues)) = segment.end else {
+                continue;
+            };
+            for exit in 0..2 {
+                if continues[exit] && !visited[id][exit] {
+                    visited[id][exit] = true;
+                    stack.push(&self.outgoing[id][exit]);
                 }
-                Direction::Up | Direction::Down => explore(grid, vis, pos.step(dir), dir),
             }
-        };
+        }
+        count
     }
-    let mut vis: PosDirSet = PosDirSet::new(grid.size());
-    explore(grid, &mut vis, pos, dir);
-    vis.count_positions()
+}
+
+/// The path of the beam from one entry point.
+pub struct Beams {
+    grid: Grid<Cell>,
+    vis: PosDirSet,
+}
+
+/// Follows the beam from `entry` through the contraption in `input`.
+pub fn beams(input: &str, entry: (Position, Direction)) -> anyhow::Result<Beams> {
+    let grid = parse_input(input)?;
+    let vis = propagate(&grid, entry);
+    Ok(Beams { grid, vis })
+}
+
+impl Beams {
+    pub fn energized(&self) -> usize {
+        self.vis.count_positions()
+    }
+
+    /// Marks energized tiles with `#` and everything else with `.`.
+    pub fn render_energized(&self) -> String {
+        self.render(|_, dirs| if dirs.is_empty() { '.' } else { '#' })
+    }
+
+    /// Draws the beams on the empty tiles, the same way as the puzzle: an arrow for the
+    /// direction the beam is heading, or the number of directions if there's more than one.
+    pub fn render_beams(&self) -> String {
+        self.render(|cell, dirs| match (cell, dirs) {
+            (Cell::Ground, [Direction::Up]) => '^',
+            (Cell::Ground, [Direction::Down]) => 'v',
+            (Cell::Ground, [Direction::Left]) => '<',
+            (Cell::Ground, [Direction::Right]) => '>',
+            (Cell::Ground, [_, ..]) => char::from_digit(dirs.len() as u32, 10).unwrap(),
+            _ => cell.symbol(),
+        })
+    }
+
+    fn render(&self, tile: impl Fn(Cell, &[Direction]) -> char) -> String {
+        let mut acc = String::new();
+        for i in 0..self.grid.height() {
+            for j in 0..self.grid.width() {
+                let dirs: Vec<Direction> = self.vis.directions(Position(i, j)).collect();
+                acc.push(tile(self.grid[(i, j)], &dirs));
+            }
+            acc.push('\n');
+        }
+        acc
+    }
+}
+
+/// How many tiles one entry point energizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub pos: Position,
+    pub dir: Direction,
+    pub energized: usize,
+}
+
+/// Part 2's search over every entry point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Survey {
+    pub entries: Vec<EntryPoint>,
+}
+
+impl Survey {
+    /// The entry point that energizes the most tiles. Ties go to the one listed first.
+    pub fn best(&self) -> Option<&EntryPoint> {
+        self.entries.iter().reduce(|best, e| {
+            if e.energized > best.energized {
+                e
+            } else {
+                best
+            }
+        })
+    }
+}
+
+/// One line per entry point, with the best one starred.
+impl std::fmt::Display for Survey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let best = self.best();
+        for e in &self.entries {
+            let marker = if Some(e) == best { '*' } else { ' ' };
+            let Position(i, j) = e.pos;
+            writeln!(
+                f,
+                "{marker} row {i}, column {j}, heading {:?}: {}",
+                e.dir, e.energized
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the energized tiles for every entry point in part 2.
+pub fn survey(input: &str) -> anyhow::Result<Survey> {
+    let grid = parse_input(input)?;
+    let counter = Counter::new(&grid, Strategy::Segments);
+    let entries = entries(&grid)
+        .into_iter()
+        .map(|(pos, dir)| EntryPoint {
+            pos,
+            dir,
+            energized: counter.count((pos, dir)),
+        })
+        .collect();
+    Ok(Survey { entries })
 }
 
 // A specialized HashSet<(Position, Direction)> that pre-allocates a Vec<bool> of the right
@@ -194,6 +501,18 @@ impl PosDirSet {
         }
         false
     }
+    fn directions(&self, Position(i, j): Position) -> impl Iterator<Item = Direction> + '_ {
+        let idx = (4 * (i * self.dims.width + j)) as usize;
+        [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]
+        .into_iter()
+        .zip(&self.bits[idx..idx + 4])
+        .filter_map(|(dir, &b)| b.then_some(dir))
+    }
     fn count_positions(&self) -> usize {
         self.bits
             .chunks(4)
@@ -233,17 +552,22 @@ enum Cell {
     SplitVert,
     SplitHoriz,
 }
-impl Debug for Cell {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Cell {
+    fn symbol(self) -> char {
         match self {
-            Self::Ground => f.write_char('.'),
-            Self::MirrorUp => f.write_char('/'),
-            Self::MirrorDown => f.write_char('\\'),
-            Self::SplitVert => f.write_char('|'),
-            Self::SplitHoriz => f.write_char('-'),
+            Self::Ground => '.',
+            Self::MirrorUp => '/',
+            Self::MirrorDown => '\\',
+            Self::SplitVert => '|',
+            Self::SplitHoriz => '-',
         }
     }
 }
+impl Debug for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_char(self.symbol())
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -293,6 +617,119 @@ mod test {
         assert_eq!(part2(SAMPLE_INPUT).unwrap(), 51);
     }
 
+    #[test]
+    fn strategies_agree() {
+        let input = std::fs::read_to_string("data/day16.input").unwrap();
+        for strategy in Strategy::ALL {
+            assert_eq!(part1_with(SAMPLE_INPUT, strategy).unwrap(), 46);
+            assert_eq!(part1_with(&input, strategy).unwrap(), 7236);
+            for threads in [1, 3] {
+                assert_eq!(part2_with(SAMPLE_INPUT, strategy, threads).unwrap(), 51);
+            }
+        }
+        let grid = parse_input(&input).unwrap();
+        let segments = Segments::new(&grid);
+        for entry in entries(&grid) {
+            assert_eq!(
+                segments.count_energized(entry),
+                count_energized(&grid, entry),
+                "{entry:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn deep_beams_dont_overflow_the_stack() {
+        // A single beam snaking through a big grid, which is far too deep to recurse on.
+        let size = 1000;
+        let rows: Vec<String> = (0..size)
+            .map(|i| {
+                (0..size)
+                    .map(|j| match (i % 2, j) {
+                        (0, j) if j == size - 1 => '\\',
+                        (1, j) if j == size - 1 => '/',
+                        (1, 0) if i + 1 < size => '/',
+                        (0, 0) if i > 0 => '\\',
+                        _ => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+        let input = rows.join("\n");
+        for strategy in [Strategy::Worklist, Strategy::Segments] {
+            assert_eq!(part1_with(&input, strategy).unwrap(), size * size);
+        }
+    }
+
+    #[test]
+    fn render_sample_input() {
+        let beams = beams(SAMPLE_INPUT, (Position(0, 0), Direction::Right)).unwrap();
+        assert_eq!(beams.energized(), 46);
+        assert_eq!(
+            beams.render_beams(),
+            r">|<<<\....
+|v-.\^....
+.v...|->>>
+.v...v^.|.
+.v...v^...
+.v...v^..\
+.v../2\\..
+<->-/vv|..
+.|<<<2-|.\
+.v//.|.v..
+"
+        );
+        assert_eq!(
+            beams.render_energized(),
+            "\
+            ######....\n\
+            .#...#....\n\
+            .#...#####\n\
+            .#...##...\n\
+            .#...##...\n\
+            .#...##...\n\
+            .#..####..\n\
+            ########..\n\
+            .#######..\n\
+            .#...#.#..\n"
+        );
+    }
+
+    #[test]
+    fn survey_sample_input() {
+        let survey = survey(SAMPLE_INPUT).unwrap();
+        assert_eq!(survey.entries.len(), 40);
+        let best = *survey.best().unwrap();
+        assert_eq!(
+            best,
+            EntryPoint {
+                pos: Position(0, 3),
+                dir: Direction::Down,
+                energized: 51
+            }
+        );
+        assert!(survey
+            .to_string()
+            .contains("* row 0, column 3, heading Down: 51\n"));
+        assert_eq!(survey.to_string().matches('*').count(), 1);
+
+        let beams = beams(SAMPLE_INPUT, (best.pos, best.dir)).unwrap();
+        assert_eq!(
+            beams.render_beams(),
+            r".|<2<\....
+|v-v\^....
+.v.v.|->>>
+.v.v.v^.|.
+.v.v.v^...
+.v.v.v^..\
+.v.v/2\\..
+<-2-/vv|..
+.|<<<2-|.\
+.v//.|.v..
+"
+        );
+    }
+
     #[test]
     fn part2_real_input() {
         assert_eq!(
@@ -194,11 +194,6 @@ impl PriorityQueue {
         self.queue.pop()
     }
 }
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)] // important that `heat_loss` comes first so that `Ord` weights it most
-struct Entry {
-    heat_loss: usize, // how much heat have we lost so far?
-    location: Location,
-}
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 struct Location {
     position: Position,
@@ -81,7 +81,7 @@ Convert the hexadecimal color codes into the correct instructions; if the Elves
 Your puzzle answer was 92556825427032.
 
 */
-use crate::grid::Direction;
+use crate::{grid::Direction, polygon::Polygon};
 use anyhow::{anyhow, bail};
 use nom::{
     branch::alt,
@@ -92,6 +92,7 @@ use nom::{
     sequence::delimited,
     IResult,
 };
+use std::fmt::Write;
 
 pub fn part1(input: &str) -> anyhow::Result<i64> {
     let instructions = parse_input(input)?;
@@ -119,24 +120,153 @@ pub fn part2(input: &str) -> anyhow::Result<i64> {
 }
 
 fn solve(moves: impl Iterator<Item = (Direction, i64)>) -> anyhow::Result<i64> {
-    let mut area: i64 = 0;
-    let mut perimeter: i64 = 0;
-    let (mut x0, mut y0) = (0, 0);
-    for (direction, distance) in moves {
-        perimeter += distance;
-        let (x1, y1) = match direction {
-            Direction::Up => (x0, y0 + distance),
-            Direction::Down => (x0, y0 - distance),
-            Direction::Left => (x0 - distance, y0),
-            Direction::Right => (x0 + distance, y0),
+    // The trench is dug through the centers of the cubes, and the lagoon is every cube whose
+    // center is inside or on the trench.
+    let lagoon = trench(moves)?.enclosed_points()?;
+    Ok(i64::try_from(lagoon)?)
+}
+
+// The path of the trench, which has to be a simple closed loop for the lagoon to make sense.
+fn trench(moves: impl Iterator<Item = (Direction, i64)>) -> anyhow::Result<Polygon> {
+    let moves: Vec<(Direction, i64)> = moves.collect();
+    let polygon = Polygon::from_moves(moves.iter().copied())?;
+    if let Some((a, b)) = polygon.self_intersection() {
+        let describe = |k: usize| {
+            let (direction, distance) = moves[k];
+            format!("step {} ({direction:?} {distance})", k + 1)
         };
-        area += (y0 + y1) * (x1 - x0);
-        (x0, y0) = (x1, y1);
+        bail!(
+            "dig plan runs into itself: {} meets {}",
+            describe(a),
+            describe(b)
+        );
+    }
+    Ok(polygon)
+}
+
+/// The most cubes [`lagoon`] will draw.
+pub const MAX_LAGOON_CELLS: usize = 1 << 22;
+
+/// A dug-out lagoon, as seen from above.
+pub struct Lagoon {
+    // Rows of cubes from top to bottom. Trench cubes have the color of the step that dug them.
+    cubes: Vec<Vec<Cube>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cube {
+    Ground,
+    Trench([u8; 3]),
+    Interior,
+}
+
+/// Digs out the lagoon for the part 1 dig plan in `input`, as long as it's small enough to
+/// draw.
+pub fn lagoon(input: &str) -> anyhow::Result<Lagoon> {
+    let instructions = parse_input(input)?;
+    let polygon = trench(
+        instructions
+            .iter()
+            .map(|i| (i.direction, i.distance as i64)),
+    )?;
+    // The polygon has y going up, and we want rows going down.
+    let (xs, ys): (Vec<i64>, Vec<i64>) = polygon.vertices().iter().copied().unzip();
+    let (left, right) = (*xs.iter().min().unwrap(), *xs.iter().max().unwrap());
+    let (bottom, top) = (*ys.iter().min().unwrap(), *ys.iter().max().unwrap());
+    let (height, width) = ((top - bottom + 1) as usize, (right - left + 1) as usize);
+    if height.saturating_mul(width) > MAX_LAGOON_CELLS {
+        bail!("lagoon is {height}x{width}, which is too big to draw");
+    }
+
+    let mut cubes = vec![vec![Cube::Ground; width]; height];
+    // Which trench cubes connect to the one above them.
+    let mut runs_up = vec![vec![false; width]; height];
+    let (mut i, mut j) = (top as usize, (-left) as usize);
+    for instruction in &instructions {
+        let color = instruction.rgb();
+        for _ in 0..instruction.distance {
+            (i, j) = match instruction.direction {
+                Direction::Up => {
+                    runs_up[i][j] = true;
+                    (i - 1, j)
+                }
+                Direction::Down => {
+                    runs_up[i + 1][j] = true;
+                    (i + 1, j)
+                }
+                Direction::Left => (i, j - 1),
+                Direction::Right => (i, j + 1),
+            };
+            cubes[i][j] = Cube::Trench(color);
+        }
+    }
+    // The trench is a simple loop, so a cube is inside it if a ray heading left crosses the
+    // trench an odd number of times. Counting only the trench cubes that connect upwards
+    // handles running along a stretch of trench: it counts once if the trench carries on
+    // through, and not at all if it turns back.
+    for (row, runs_up) in cubes.iter_mut().zip(&runs_up) {
+        let mut inside = false;
+        for (cube, &runs_up) in row.iter_mut().zip(runs_up) {
+            match cube {
+                Cube::Trench(_) => inside ^= runs_up,
+                _ if inside => *cube = Cube::Interior,
+                _ => {}
+            }
+        }
+    }
+    Ok(Lagoon { cubes })
+}
+
+impl Lagoon {
+    /// The number of cubes dug out, including the trench.
+    pub fn volume(&self) -> usize {
+        self.cubes
+            .iter()
+            .flatten()
+            .filter(|&&c| c != Cube::Ground)
+            .count()
+    }
+
+    /// Draws the lagoon the same way as the puzzle. With `filled`, the interior is dug out
+    /// too; otherwise it's just the trench.
+    pub fn render(&self, filled: bool) -> String {
+        let mut acc = String::new();
+        for row in &self.cubes {
+            for &cube in row {
+                acc.push(match cube {
+                    Cube::Trench(_) => '#',
+                    Cube::Interior if filled => '#',
+                    _ => '.',
+                });
+            }
+            acc.push('\n');
+        }
+        acc
     }
-    if (x0, y0) != (0, 0) {
-        bail!("instructions did not form a closed loop, started at (0, 0), ended at ({x0},{y0})");
+
+    /// Draws the lagoon as an SVG, one square per cube, with the trench painted in its colors.
+    pub fn to_svg(&self) -> String {
+        let (height, width) = (self.cubes.len(), self.cubes[0].len());
+        let mut acc = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {width} {height}\" shape-rendering=\"crispEdges\">\n"
+        );
+        for (i, row) in self.cubes.iter().enumerate() {
+            for (j, &cube) in row.iter().enumerate() {
+                let fill = match cube {
+                    Cube::Ground => continue,
+                    Cube::Trench([r, g, b]) => format!("#{r:02x}{g:02x}{b:02x}"),
+                    Cube::Interior => "#404040".to_owned(),
+                };
+                writeln!(
+                    acc,
+                    "<rect x=\"{j}\" y=\"{i}\" width=\"1\" height=\"1\" fill=\"{fill}\"/>"
+                )
+                .unwrap();
+            }
+        }
+        acc.push_str("</svg>\n");
+        acc
     }
-    Ok(area / 2 + perimeter / 2 + 1)
 }
 
 #[derive(Debug, Clone)]
@@ -145,6 +275,16 @@ struct Instruction {
     distance: u32,
     color: [u8; 6],
 }
+impl Instruction {
+    fn rgb(&self) -> [u8; 3] {
+        let hex = |k: usize| {
+            let digits = std::str::from_utf8(&self.color[2 * k..2 * k + 2]).unwrap();
+            u8::from_str_radix(digits, 16).unwrap()
+        };
+        [hex(0), hex(1), hex(2)]
+    }
+}
+
 fn parse_input(input: &str) -> anyhow::Result<Vec<Instruction>> {
     let (_, instructions) =
         all_consuming(delimited(multispace0, instructions_parser, multispace0))(input)
@@ -219,6 +359,101 @@ mod test {
         );
     }
 
+    #[test]
+    fn render_sample_input() {
+        let lagoon = lagoon(SAMPLE_INPUT).unwrap();
+        assert_eq!(
+            lagoon.render(false),
+            "\
+            #######\n\
+            #.....#\n\
+            ###...#\n\
+            ..#...#\n\
+            ..#...#\n\
+            ###.###\n\
+            #...#..\n\
+            ##..###\n\
+            .#....#\n\
+            .######\n"
+        );
+        assert_eq!(
+            lagoon.render(true),
+            "\
+            #######\n\
+            #######\n\
+            #######\n\
+            ..#####\n\
+            ..#####\n\
+            #######\n\
+            #####..\n\
+            #######\n\
+            .######\n\
+            .######\n"
+        );
+        assert_eq!(lagoon.volume(), 62);
+
+        let svg = lagoon.to_svg();
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect").count(), 62);
+        // The first step digs right from the top-left corner.
+        assert!(svg.contains(r##"<rect x="1" y="0" width="1" height="1" fill="#70c710"/>"##));
+        assert_eq!(svg.matches("#404040").count(), 62 - 38);
+    }
+
+    #[test]
+    fn render_real_input() {
+        let input = std::fs::read_to_string("data/day18.input").unwrap();
+        assert_eq!(lagoon(&input).unwrap().volume(), 49061);
+    }
+
+    #[test]
+    fn self_intersecting_plans() {
+        // Crosses over the first step.
+        let crossing = "
+            R 4 (#000000)
+            D 2 (#000000)
+            L 2 (#000000)
+            U 4 (#000000)
+            L 2 (#000000)
+            D 2 (#000000)
+        ";
+        assert_eq!(
+            part1(crossing).unwrap_err().to_string(),
+            "dig plan runs into itself: step 1 (Right 4) meets step 4 (Up 4)"
+        );
+        // Digs straight back along the previous step.
+        let overlapping = "
+            R 4 (#000000)
+            L 2 (#000000)
+            D 2 (#000000)
+            L 2 (#000000)
+            U 2 (#000000)
+        ";
+        assert!(part1(overlapping).is_err());
+        assert!(lagoon(overlapping).is_err());
+        // Touches itself at a corner.
+        let touching = "
+            R 2 (#000000)
+            D 2 (#000000)
+            R 2 (#000000)
+            D 2 (#000000)
+            L 2 (#000000)
+            U 2 (#000000)
+            L 2 (#000000)
+            U 2 (#000000)
+        ";
+        assert!(part1(touching).is_err());
+        // A zero-length step doesn't run into anything.
+        let standing_still = "
+            R 2 (#000000)
+            D 0 (#000000)
+            D 2 (#000000)
+            L 2 (#000000)
+            U 2 (#000000)
+        ";
+        assert_eq!(part1(standing_still).unwrap(), 9);
+    }
+
     #[test]
     fn part2_sample_input() {
         assert_eq!(part2(SAMPLE_INPUT).unwrap(), 952408144115);
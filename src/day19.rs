@@ -1,13 +1,16 @@
 use anyhow::{anyhow, bail};
-use std::{collections::HashMap, ops::RangeInclusive};
+use itertools::Itertools;
+use std::{collections::HashMap, fmt::Display, ops::RangeInclusive};
+
+use crate::interval::IntervalSet;
 
 use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alpha1, digit1, multispace0, multispace1, newline, space0},
     combinator::{all_consuming, map, map_res, value},
-    multi::{separated_list0, separated_list1},
-    sequence::{delimited, separated_pair},
+    multi::{many0, separated_list1},
+    sequence::{delimited, pair, terminated},
     IResult,
 };
 
@@ -79,61 +82,246 @@ Consider only your list of workflows; the list of part ratings that the Elves wa
 */
 pub fn part1(input: &str) -> anyhow::Result<u32> {
     let input = parse_input(input)?;
-    let wfs: HashMap<String, Workflow> = input
-        .workflows
+    let program = Program::compile(input.workflows)?;
+    let total = input
+        .items
+        .iter()
+        .filter(|item| program.accepts(item))
+        .map(|item| item.kvs.iter().map(|(_, v)| v).sum::<u32>())
+        .sum();
+    Ok(total)
+}
+
+pub fn part2(input: &str) -> anyhow::Result<u64> {
+    let program = compile(input)?;
+    program.accepted_volume(&Items::uniform(["x", "m", "a", "s"], 1..=4000))
+}
+
+/// Parses the workflows out of a puzzle input and compiles them.
+pub fn compile(input: &str) -> anyhow::Result<Program> {
+    Program::compile(parse_input(input)?.workflows)
+}
+
+/// Runs every part in the input through the workflows, recording how each one was sorted.
+pub fn explain(input: &str) -> anyhow::Result<Vec<(Item, Trace)>> {
+    let input = parse_input(input)?;
+    let program = Program::compile(input.workflows)?;
+    Ok(input
+        .items
         .into_iter()
-        .map(|w| (w.name.clone(), w))
-        .collect();
-    let accept = |item: &Item| -> anyhow::Result<bool> {
-        let mut label = "in".to_owned();
-        for _ in 0..wfs.len() {
-            let w = wfs
-                .get(&label)
-                .ok_or_else(|| anyhow!("no workflow with label {label}"))?;
-            match w.destination(item) {
-                Destination::Accept => return Ok(true),
-                Destination::Reject => return Ok(false),
-                Destination::Workflow(next) => label = next,
+        .map(|item| {
+            let trace = program.explain(&item);
+            (item, trace)
+        })
+        .collect())
+}
+
+/// The workflows rooted at `in`, flattened into the disjoint boxes of ratings that they accept.
+#[derive(Debug)]
+pub struct Program {
+    workflows: HashMap<String, Workflow>,
+    accepted: Vec<Items>,
+    pub analysis: Analysis,
+}
+
+/// Every rule that a part was checked against on its way from `in` to `A` or `R`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    pub accepted: bool,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub workflow: String,
+    pub rule: usize,
+    /// `None` for a workflow's fallback rule, which always matches.
+    pub condition: Option<String>,
+    pub matched: bool,
+}
+// Formats the trace the way the puzzle does, e.g. `in -> qqz -> qs -> lnx -> A`.
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for w in self.steps.iter().map(|s| &s.workflow).dedup() {
+            write!(f, "{w} -> ")?;
+        }
+        f.write_str(if self.accepted { "A" } else { "R" })
+    }
+}
+/// Things that are legal but suspicious about a set of workflows.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Analysis {
+    /// (workflow, rule index) pairs that no part can ever reach. The fallback is rule `transitions.len()`.
+    pub unreachable_rules: Vec<(String, usize)>,
+    /// Workflows that no part can ever reach from `in`.
+    pub dead_workflows: Vec<String>,
+    /// Every cycle of workflows, e.g. `[a, b]` for `a -> b -> a`, starting from its
+    /// alphabetically first workflow. These are all impossible to follow, since `compile`
+    /// rejects any cycle that a part could get stuck in.
+    pub cycles: Vec<Vec<String>>,
+}
+impl Program {
+    /// Symbolically runs every possible part through the workflows. Undefined labels and
+    /// cycles that some part could actually get stuck in are errors; other cycles are listed
+    /// in the [`Analysis`].
+    pub fn compile(workflows: Vec<Workflow>) -> anyhow::Result<Program> {
+        let wfs: HashMap<String, Workflow> =
+            workflows.into_iter().map(|w| (w.name.clone(), w)).collect();
+        for w in wfs.values() {
+            for d in w
+                .transitions
+                .iter()
+                .map(|t| &t.destination)
+                .chain([&w.fallback])
+            {
+                if let Destination::Workflow(label) = d {
+                    if !wfs.contains_key(label) {
+                        bail!("workflow {} refers to undefined workflow {label}", w.name);
+                    }
+                }
             }
         }
-        bail!("no decision after {} iterations", wfs.len())
-    };
-    let mut total = 0;
-    for item in input.items {
-        if accept(&item)? {
-            total += item.kvs.values().sum::<u32>();
+        if !wfs.contains_key("in") {
+            bail!("no workflow with label in");
         }
+
+        let mut reached: HashMap<&str, Vec<bool>> = wfs
+            .values()
+            .map(|w| (w.name.as_str(), vec![false; w.transitions.len() + 1]))
+            .collect();
+        let mut accepted = Vec::new();
+        let mut stack = vec![(vec!["in"], Items::default())];
+        while let Some((path, items)) = stack.pop() {
+            let label = *path.last().unwrap();
+            let w = &wfs[label];
+            for (idx, destination, child) in w.destinations(items) {
+                reached.get_mut(label).unwrap()[idx] = true;
+                match destination {
+                    Destination::Accept => accepted.push(child),
+                    Destination::Reject => {}
+                    Destination::Workflow(next) => {
+                        if path.contains(&next.as_str()) {
+                            bail!("cycle: {} -> {next}", path.join(" -> "));
+                        }
+                        let mut path = path.clone();
+                        path.push(next);
+                        stack.push((path, child));
+                    }
+                }
+            }
+        }
+
+        let mut analysis = Analysis::default();
+        for (name, rules) in reached {
+            if rules.iter().all(|&r| !r) {
+                analysis.dead_workflows.push(name.to_owned());
+                continue;
+            }
+            for (idx, _) in rules.iter().enumerate().filter(|(_, &r)| !r) {
+                analysis.unreachable_rules.push((name.to_owned(), idx));
+            }
+        }
+        analysis.dead_workflows.sort();
+        analysis.unreachable_rules.sort();
+        analysis.cycles = cycles(&wfs);
+        Ok(Program {
+            workflows: wfs,
+            accepted,
+            analysis,
+        })
+    }
+
+    /// Runs `item` through the workflows one rule at a time. `compile` has already ruled
+    /// out any cycle that a part could get stuck in, so this always terminates.
+    pub fn explain(&self, item: &Item) -> Trace {
+        let mut steps = Vec::new();
+        let mut label = "in";
+        loop {
+            let w = &self.workflows[label];
+            let mut destination = None;
+            for (rule, t) in w.transitions.iter().enumerate() {
+                let matched = t.condition.eval(item);
+                steps.push(Step {
+                    workflow: label.to_owned(),
+                    rule,
+                    condition: Some(t.condition.to_string()),
+                    matched,
+                });
+                if matched {
+                    destination = Some(&t.destination);
+                    break;
+                }
+            }
+            let destination = destination.unwrap_or_else(|| {
+                steps.push(Step {
+                    workflow: label.to_owned(),
+                    rule: w.transitions.len(),
+                    condition: None,
+                    matched: true,
+                });
+                &w.fallback
+            });
+            let accepted = match destination {
+                Destination::Accept => true,
+                Destination::Reject => false,
+                Destination::Workflow(next) => {
+                    label = next;
+                    continue;
+                }
+            };
+            return Trace { steps, accepted };
+        }
+    }
+
+    pub fn accepts(&self, item: &Item) -> bool {
+        self.accepted.iter().any(|items| items.contains(item))
+    }
+
+    /// How many distinct parts within `bounds` are accepted. `bounds` has to constrain every
+    /// attribute that the workflows test, or there would be no sensible count to give.
+    pub fn accepted_volume(&self, bounds: &Items) -> anyhow::Result<u64> {
+        let mut keys: Vec<&str> = Vec::new();
+        for w in self.workflows.values() {
+            for t in &w.transitions {
+                t.condition.keys(&mut keys);
+            }
+        }
+        if let Some(k) = keys.into_iter().find(|k| !bounds.kvs.contains_key(*k)) {
+            bail!("bounds don't say which values of {k} to count");
+        }
+        self.accepted.iter().try_fold(0u64, |total, items| {
+            items
+                .intersect(bounds)
+                .count()
+                .and_then(|n| total.checked_add(n))
+                .ok_or_else(|| anyhow!("accepted volume overflows u64"))
+        })
     }
-    Ok(total)
 }
 
-pub fn part2(input: &str) -> anyhow::Result<u64> {
-    let input = parse_input(input)?;
-    let wfs: HashMap<String, Workflow> = input
-        .workflows
-        .into_iter()
-        .map(|w| (w.name.clone(), w))
-        .collect();
-    let full: HashMap<Key, RangeInclusive<u32>> = [Key::X, Key::M, Key::A, Key::S]
-        .into_iter()
-        .map(|k| (k, 1..=4000))
-        .collect();
-
-    let mut stack = vec![("in".to_owned(), Items { kvs: full })];
-    let mut total = 0;
-    while let Some((label, items)) = stack.pop() {
-        let w = wfs
-            .get(&label)
-            .ok_or_else(|| anyhow!("no workflow with label {label}"))?;
-        for (destination, child) in w.destinations(items) {
-            match destination {
-                Destination::Accept => total += child.count(),
-                Destination::Reject => {}
-                Destination::Workflow(next) => stack.push((next, child)),
+// Every elementary cycle in the graph of workflows, in sorted order. Each one is only
+// searched for from its alphabetically first workflow, so it's found exactly once.
+fn cycles(wfs: &HashMap<String, Workflow>) -> Vec<Vec<String>> {
+    fn extend<'a>(
+        wfs: &'a HashMap<String, Workflow>,
+        path: &mut Vec<&'a str>,
+        acc: &mut Vec<Vec<String>>,
+    ) {
+        let start = path[0];
+        for next in wfs[*path.last().unwrap()].successors() {
+            if next == start {
+                acc.push(path.iter().map(|&w| w.to_owned()).collect());
+            } else if next > start && !path.contains(&next) {
+                path.push(next);
+                extend(wfs, path, acc);
+                path.pop();
             }
         }
     }
-    Ok(total)
+    let mut acc = Vec::new();
+    for start in wfs.keys().sorted() {
+        extend(wfs, &mut vec![start.as_str()], &mut acc);
+    }
+    acc
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -141,91 +329,122 @@ struct Input {
     workflows: Vec<Workflow>,
     items: Vec<Item>,
 }
+/// A single part. Every comparison against an attribute that it doesn't list is false, so
+/// rules that test it are skipped.
 #[derive(Clone, PartialEq, Eq, Debug)]
-struct Item {
-    kvs: HashMap<Key, u32>,
+pub struct Item {
+    pub kvs: Vec<(String, u32)>,
 }
-#[derive(Clone, PartialEq, Eq, Debug)]
-struct Items {
-    kvs: HashMap<Key, RangeInclusive<u32>>,
+impl Item {
+    fn get(&self, key: &str) -> Option<u32> {
+        self.kvs.iter().find(|(k, _)| k == key).map(|&(_, v)| v)
+    }
+}
+impl Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kvs = self.kvs.iter().map(|(k, v)| format!("{k}={v}")).join(",");
+        write!(f, "{{{kvs}}}")
+    }
+}
+/// A box of parts: every attribute independently takes any value from its set. An attribute
+/// that isn't listed may take any `u32` value, or be missing altogether.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Items {
+    pub kvs: HashMap<String, IntervalSet>,
 }
 impl Items {
-    fn count(&self) -> u64 {
+    /// Every part whose `keys` all fall within `range`.
+    pub fn uniform<'a>(
+        keys: impl IntoIterator<Item = &'a str>,
+        range: RangeInclusive<u32>,
+    ) -> Items {
+        let vs = IntervalSet::from(*range.start() as i64..*range.end() as i64 + 1);
+        Items {
+            kvs: keys
+                .into_iter()
+                .map(|k| (k.to_owned(), vs.clone()))
+                .collect(),
+        }
+    }
+    fn get(&self, key: &str) -> IntervalSet {
+        self.kvs.get(key).cloned().unwrap_or_else(all_values)
+    }
+    // `None` if the count doesn't fit in a u64.
+    fn count(&self) -> Option<u64> {
         self.kvs
             .values()
-            .map(|vs| (vs.end() - vs.start() + 1) as u64)
-            .product()
+            .try_fold(1u64, |n, vs| n.checked_mul(vs.size() as u64))
+    }
+    fn is_empty(&self) -> bool {
+        self.kvs.values().any(|vs| vs.is_empty())
+    }
+    fn contains(&self, item: &Item) -> bool {
+        self.kvs
+            .iter()
+            .all(|(k, vs)| vs.contains(item.get(k).map_or(MISSING, i64::from)))
+    }
+    fn intersect(&self, other: &Items) -> Items {
+        let mut kvs = self.kvs.clone();
+        for (k, vs) in &other.kvs {
+            let cur = kvs.entry(k.clone()).or_insert_with(all_values);
+            *cur = cur.intersection(vs);
+        }
+        Items { kvs }
     }
 }
-
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-enum Key {
-    X,
-    M,
-    A,
-    S,
+// Stands in for an attribute that a part doesn't have. No comparison is ever satisfied by it.
+const MISSING: i64 = -1;
+fn all_values() -> IntervalSet {
+    IntervalSet::from(MISSING..u32::MAX as i64 + 1)
 }
+
 #[derive(Clone, PartialEq, Eq, Debug)]
-struct Workflow {
+pub struct Workflow {
     name: String,
     transitions: Vec<Transition>,
     fallback: Destination,
 }
 impl Workflow {
-    fn destination(&self, item: &Item) -> Destination {
-        for Transition {
-            condition,
-            destination,
-        } in &self.transitions
-        {
-            let Some(&v) = item.kvs.get(&condition.key) else {
-                continue;
-            };
-            let result = match condition.op {
-                BinaryOp::LT => v < condition.value,
-                BinaryOp::GT => v > condition.value,
-            };
-            if result {
-                return destination.clone();
-            }
-        }
-        self.fallback.clone()
+    // The workflows this one can send parts to, in sorted order.
+    fn successors(&self) -> impl Iterator<Item = &str> {
+        self.transitions
+            .iter()
+            .map(|t| &t.destination)
+            .chain([&self.fallback])
+            .filter_map(|d| match d {
+                Destination::Workflow(label) => Some(label.as_str()),
+                _ => None,
+            })
+            .sorted()
+            .dedup()
     }
 
-    fn destinations(&self, items: Items) -> Vec<(Destination, Items)> {
-        let mut leftovers = items;
+    // Splits `items` up according to which rule each part would match. Each non-empty piece
+    // is returned along with the index of the rule it matched (the fallback is last).
+    fn destinations(&self, items: Items) -> Vec<(usize, &Destination, Items)> {
+        let mut leftovers = vec![items];
         let mut results = Vec::new();
-        for Transition {
-            condition,
-            destination,
-        } in &self.transitions
+        for (
+            idx,
+            Transition {
+                condition,
+                destination,
+            },
+        ) in self.transitions.iter().enumerate()
         {
-            let vs = leftovers.kvs.get(&condition.key).cloned().unwrap();
-            if !vs.contains(&condition.value) {
-                continue;
+            let mut rest = Vec::new();
+            for items in leftovers {
+                let (matched, unmatched) = condition.split(items);
+                results.extend(matched.into_iter().map(|child| (idx, destination, child)));
+                rest.extend(unmatched);
             }
-            let mut child = leftovers.clone();
-            match condition.op {
-                BinaryOp::LT => {
-                    child
-                        .kvs
-                        .insert(condition.key, *vs.start()..=condition.value - 1);
-                    leftovers
-                        .kvs
-                        .insert(condition.key, condition.value..=*vs.end());
-                }
-                BinaryOp::GT => {
-                    leftovers
-                        .kvs
-                        .insert(condition.key, *vs.start()..=condition.value);
-                    child
-                        .kvs
-                        .insert(condition.key, condition.value + 1..=*vs.end());
-                }
-            };
-            results.push((destination.clone(), child));
+            leftovers = rest;
         }
-        results.push((self.fallback.clone(), leftovers));
+        results.extend(
+            leftovers
+                .into_iter()
+                .map(|child| (self.transitions.len(), &self.fallback, child)),
+        );
         results
     }
 }
@@ -235,15 +454,138 @@ struct Transition {
     destination: Destination,
 }
 #[derive(Clone, PartialEq, Eq, Debug)]
-struct Condition {
-    key: Key,
-    op: BinaryOp,
-    value: u32,
+enum Condition {
+    Compare {
+        key: String,
+        op: BinaryOp,
+        value: u32,
+    },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+impl Condition {
+    // Adds every attribute that this condition looks at to `keys`.
+    fn keys<'a>(&'a self, keys: &mut Vec<&'a str>) {
+        match self {
+            Condition::Compare { key, .. } => keys.push(key),
+            Condition::All(cs) | Condition::Any(cs) => cs.iter().for_each(|c| c.keys(keys)),
+        }
+    }
+    fn eval(&self, item: &Item) -> bool {
+        match self {
+            Condition::Compare { key, op, value } => {
+                let Some(v) = item.get(key) else {
+                    return false;
+                };
+                match op {
+                    BinaryOp::LT => v < *value,
+                    BinaryOp::GT => v > *value,
+                    BinaryOp::LE => v <= *value,
+                    BinaryOp::GE => v >= *value,
+                    BinaryOp::EQ => v == *value,
+                    BinaryOp::NE => v != *value,
+                }
+            }
+            Condition::All(cs) => cs.iter().all(|c| c.eval(item)),
+            Condition::Any(cs) => cs.iter().any(|c| c.eval(item)),
+        }
+    }
+
+    // Splits `items` into disjoint boxes of parts that do and do not satisfy this condition.
+    fn split(&self, items: Items) -> (Vec<Items>, Vec<Items>) {
+        match self {
+            Condition::Compare { key, op, value } => {
+                let v = *value as i64;
+                let satisfying = match op {
+                    BinaryOp::LT => IntervalSet::from(0..v),
+                    BinaryOp::GT => IntervalSet::from(v + 1..u32::MAX as i64 + 1),
+                    BinaryOp::LE => IntervalSet::from(0..v + 1),
+                    BinaryOp::GE => IntervalSet::from(v..u32::MAX as i64 + 1),
+                    BinaryOp::EQ => IntervalSet::from(v..v + 1),
+                    BinaryOp::NE => IntervalSet::from(0..u32::MAX as i64 + 1)
+                        .difference(&IntervalSet::from(v..v + 1)),
+                };
+                let vs = items.get(key);
+                let mut matched = items.clone();
+                matched
+                    .kvs
+                    .insert(key.clone(), vs.intersection(&satisfying));
+                let mut unmatched = items;
+                unmatched
+                    .kvs
+                    .insert(key.clone(), vs.difference(&satisfying));
+                (
+                    Some(matched)
+                        .filter(|i| !i.is_empty())
+                        .into_iter()
+                        .collect(),
+                    Some(unmatched)
+                        .filter(|i| !i.is_empty())
+                        .into_iter()
+                        .collect(),
+                )
+            }
+            // Parts that fail any clause are out; whatever survives all of them is in.
+            Condition::All(cs) => {
+                let (mut matched, mut unmatched) = (vec![items], Vec::new());
+                for c in cs {
+                    let mut next = Vec::new();
+                    for items in matched {
+                        let (m, u) = c.split(items);
+                        next.extend(m);
+                        unmatched.extend(u);
+                    }
+                    matched = next;
+                }
+                (matched, unmatched)
+            }
+            // Parts that pass any clause are in; whatever fails all of them is out.
+            Condition::Any(cs) => {
+                let (mut matched, mut unmatched) = (Vec::new(), vec![items]);
+                for c in cs {
+                    let mut next = Vec::new();
+                    for items in unmatched {
+                        let (m, u) = c.split(items);
+                        matched.extend(m);
+                        next.extend(u);
+                    }
+                    unmatched = next;
+                }
+                (matched, unmatched)
+            }
+        }
+    }
+}
+// Formats conditions the same way they're written in the input.
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Compare { key, op, value } => write!(f, "{key}{op}{value}"),
+            Condition::All(cs) => f.write_str(&cs.iter().join("&&")),
+            Condition::Any(cs) => f.write_str(&cs.iter().join("||")),
+        }
+    }
+}
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BinaryOp::LT => "<",
+            BinaryOp::GT => ">",
+            BinaryOp::LE => "<=",
+            BinaryOp::GE => ">=",
+            BinaryOp::EQ => "==",
+            BinaryOp::NE => "!=",
+        })
+    }
 }
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum BinaryOp {
     LT,
     GT,
+    LE,
+    GE,
+    EQ,
+    NE,
 }
 #[derive(Clone, PartialEq, Eq, Debug)]
 enum Destination {
@@ -270,9 +612,8 @@ fn workflow_parser(input: &str) -> IResult<&str, Workflow> {
     let (input, name) = alpha1(input)?;
     let (input, (transitions, fallback)) = delimited(
         tag("{"),
-        separated_pair(
-            separated_list0(tag(","), transition_parser),
-            tag(","),
+        pair(
+            many0(terminated(transition_parser, tag(","))),
             destination_parser,
         ),
         tag("}"),
@@ -287,19 +628,56 @@ fn workflow_parser(input: &str) -> IResult<&str, Workflow> {
     ))
 }
 fn transition_parser(input: &str) -> IResult<&str, Transition> {
-    let (input, key) = key_parser(input)?;
-    let (input, op) = alt((value(BinaryOp::LT, tag("<")), value(BinaryOp::GT, tag(">"))))(input)?;
-    let (input, value) = map_res(digit1, str::parse)(input)?;
+    let (input, condition) = condition_parser(input)?;
     let (input, _) = tag(":")(input)?;
     let (input, destination) = destination_parser(input)?;
     Ok((
         input,
         Transition {
-            condition: Condition { key, op, value },
+            condition,
             destination,
         },
     ))
 }
+// `&&` binds tighter than `||`, so `a<1||b<2&&c<3` means `a<1||(b<2&&c<3)`.
+fn condition_parser(input: &str) -> IResult<&str, Condition> {
+    let (input, mut any) = separated_list1(tag("||"), all_parser)(input)?;
+    let condition = if any.len() == 1 {
+        any.pop().unwrap()
+    } else {
+        Condition::Any(any)
+    };
+    Ok((input, condition))
+}
+fn all_parser(input: &str) -> IResult<&str, Condition> {
+    let (input, mut all) = separated_list1(tag("&&"), compare_parser)(input)?;
+    let condition = if all.len() == 1 {
+        all.pop().unwrap()
+    } else {
+        Condition::All(all)
+    };
+    Ok((input, condition))
+}
+fn compare_parser(input: &str) -> IResult<&str, Condition> {
+    let (input, key) = alpha1(input)?;
+    let (input, op) = alt((
+        value(BinaryOp::LE, tag("<=")),
+        value(BinaryOp::GE, tag(">=")),
+        value(BinaryOp::EQ, tag("==")),
+        value(BinaryOp::NE, tag("!=")),
+        value(BinaryOp::LT, tag("<")),
+        value(BinaryOp::GT, tag(">")),
+    ))(input)?;
+    let (input, value) = map_res(digit1, str::parse)(input)?;
+    Ok((
+        input,
+        Condition::Compare {
+            key: key.to_owned(),
+            op,
+            value,
+        },
+    ))
+}
 fn destination_parser(input: &str) -> IResult<&str, Destination> {
     alt((
         value(Destination::Accept, tag("A")),
@@ -307,14 +685,6 @@ fn destination_parser(input: &str) -> IResult<&str, Destination> {
         map(alpha1, |s: &str| Destination::Workflow(s.to_owned())),
     ))(input)
 }
-fn key_parser(input: &str) -> IResult<&str, Key> {
-    alt((
-        value(Key::X, tag("x")),
-        value(Key::M, tag("m")),
-        value(Key::A, tag("a")),
-        value(Key::S, tag("s")),
-    ))(input)
-}
 fn items_parser(input: &str) -> IResult<&str, Vec<Item>> {
     separated_list1(delimited(space0, newline, space0), item_parser)(input)
 }
@@ -327,11 +697,11 @@ fn item_parser(input: &str) -> IResult<&str, Item> {
         },
     ))
 }
-fn kv_parser(input: &str) -> IResult<&str, (Key, u32)> {
-    let (input, key) = key_parser(input)?;
+fn kv_parser(input: &str) -> IResult<&str, (String, u32)> {
+    let (input, key) = alpha1(input)?;
     let (input, _) = tag("=")(input)?;
     let (input, value) = map_res(digit1, str::parse)(input)?;
-    Ok((input, (key, value)))
+    Ok((input, (key.to_owned(), value)))
 }
 
 #[cfg(test)]
@@ -365,6 +735,209 @@ mod test {
         assert_eq!(input.items.len(), 5);
     }
 
+    #[test]
+    fn compile_sample_input() {
+        let program = compile(SAMPLE_INPUT).unwrap();
+        // `gd` rejects everything, and `lnx` accepts everything, either way.
+        assert_eq!(program.analysis, Analysis::default());
+        let bounds = Items::uniform(["x", "m", "a", "s"], 1..=4000);
+        assert_eq!(program.accepted_volume(&bounds).unwrap(), 167409079868000);
+        let mut narrow = bounds.clone();
+        narrow
+            .kvs
+            .insert("s".to_owned(), IntervalSet::from(1..1351));
+        narrow
+            .kvs
+            .insert("a".to_owned(), IntervalSet::from(1..2006));
+        narrow
+            .kvs
+            .insert("x".to_owned(), IntervalSet::from(1..1416));
+        // in -> px -> qkq -> A
+        assert_eq!(
+            program.accepted_volume(&narrow).unwrap(),
+            1415 * 4000 * 2005 * 1350
+        );
+    }
+
+    #[test]
+    fn compile_finds_problems() {
+        let workflows = |input: &str| {
+            parse_input(&format!("{input}\n\n{{x=1}}"))
+                .unwrap()
+                .workflows
+        };
+        let err = |input: &str| Program::compile(workflows(input)).unwrap_err().to_string();
+        assert_eq!(
+            err("in{x<10:A,foo}"),
+            "workflow in refers to undefined workflow foo"
+        );
+        assert_eq!(
+            err("in{x<10:A,a}\na{b}\nb{m>5:a,R}"),
+            "cycle: in -> a -> b -> a"
+        );
+        assert_eq!(err("foo{A}"), "no workflow with label in");
+
+        // The cycle is impossible to actually follow, so it's fine.
+        let program =
+            Program::compile(workflows("in{x<10:a,R}\na{x>20:in,x<5:A,x<3:R,R}\nb{A}")).unwrap();
+        assert_eq!(
+            program.analysis,
+            Analysis {
+                unreachable_rules: vec![("a".to_owned(), 0), ("a".to_owned(), 2)],
+                dead_workflows: vec!["b".to_owned()],
+                cycles: vec![vec!["a".to_owned(), "in".to_owned()]],
+            }
+        );
+        assert_eq!(
+            program
+                .accepted_volume(&Items::uniform(["x", "m", "a", "s"], 1..=100))
+                .unwrap(),
+            4 * 100 * 100 * 100
+        );
+        // A rule that can never match sends parts back to the workflow it's in.
+        let program = Program::compile(workflows("in{x<10:A,x<5:in,b}\nb{m>5:A,R}")).unwrap();
+        assert_eq!(
+            program.analysis,
+            Analysis {
+                unreachable_rules: vec![("in".to_owned(), 1)],
+                dead_workflows: vec![],
+                cycles: vec![vec!["in".to_owned()]],
+            }
+        );
+    }
+
+    #[test]
+    fn accepted_volume_errors() {
+        let program = compile("in{x<10:A,z<5:A,R}\n\n{x=1}").unwrap();
+        let err = program
+            .accepted_volume(&Items::uniform(["x"], 1..=20))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bounds don't say which values of z to count"
+        );
+        assert_eq!(
+            program
+                .accepted_volume(&Items::uniform(["x", "z"], 1..=20))
+                .unwrap(),
+            9 * 20 + 11 * 4
+        );
+
+        let program = compile(SAMPLE_INPUT).unwrap();
+        assert!(program
+            .accepted_volume(&Items::uniform(["x"], 1..=4000))
+            .is_err());
+        let everything = Items::uniform(["x", "m", "a", "s"], 0..=u32::MAX);
+        assert_eq!(
+            program
+                .accepted_volume(&everything)
+                .unwrap_err()
+                .to_string(),
+            "accepted volume overflows u64"
+        );
+    }
+
+    #[test]
+    fn missing_attributes_skip_rules() {
+        let input = "
+            in{x<10:R,x!=3||m>5:foo,A}
+            foo{m<=5:A,R}
+
+            {m=1}
+            {m=6}
+            {x=3,m=6}
+            {x=12}
+            {x=12,m=2}
+        ";
+        let program = compile(input).unwrap();
+        let items = parse_input(input).unwrap().items;
+        let accepted: Vec<bool> = items.iter().map(|item| program.accepts(item)).collect();
+        assert_eq!(accepted, vec![true, false, false, false, true]);
+        // The symbolic boxes agree with running each part through the rules.
+        for item in &items {
+            assert_eq!(
+                program.explain(item).accepted,
+                program.accepts(item),
+                "{item}"
+            );
+        }
+    }
+
+    #[test]
+    fn richer_conditions() {
+        let input = "
+            in{x!=5&&m>=10:A,x==5||m<=2:foo,R}
+            foo{s>0&&s<4||s>=4000:A,R}
+
+            {x=4,m=10}
+            {x=5,m=10}
+            {x=5,m=10,s=3}
+            {x=6,m=2,s=4000}
+            {x=6,m=3,s=4000}
+        ";
+        let program = compile(input).unwrap();
+        assert_eq!(program.analysis, Analysis::default());
+        let accepted: Vec<bool> = parse_input(input)
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| program.accepts(item))
+            .collect();
+        assert_eq!(accepted, vec![true, false, true, true, false]);
+
+        let bounds = Items::uniform(["x", "m", "s"], 1..=10);
+        // in: x!=5 && m>=10 accepts 9 * 1 * 10
+        // foo: (x==5 with any m) or (x!=5 and m<=2), then s in 1..=3
+        let to_foo = 10 + 9 * 2;
+        assert_eq!(
+            program.accepted_volume(&bounds).unwrap(),
+            9 * 10 + to_foo * 3
+        );
+        assert_eq!(part1(input).unwrap(), 4 + 10 + 5 + 10 + 3 + 6 + 2 + 4000);
+    }
+
+    #[test]
+    fn explain_sample_input() {
+        let traces: Vec<String> = explain(SAMPLE_INPUT)
+            .unwrap()
+            .into_iter()
+            .map(|(item, trace)| format!("{item}: {trace}"))
+            .collect();
+        assert_eq!(
+            traces,
+            vec![
+                "{x=787,m=2655,a=1222,s=2876}: in -> qqz -> qs -> lnx -> A",
+                "{x=1679,m=44,a=2067,s=496}: in -> px -> rfg -> gd -> R",
+                "{x=2036,m=264,a=79,s=2244}: in -> qqz -> hdj -> pv -> A",
+                "{x=2461,m=1339,a=466,s=291}: in -> px -> qkq -> crn -> R",
+                "{x=2127,m=1623,a=2188,s=1013}: in -> px -> rfg -> A",
+            ]
+        );
+    }
+
+    #[test]
+    fn explain_records_every_rule() {
+        let input = "in{x<5&&m>=2:R,x>=5||m!=1:foo,A}\nfoo{A}\n\n{x=7,m=1}";
+        let (_, trace) = explain(input).unwrap().pop().unwrap();
+        let step = |workflow: &str, rule, condition: Option<&str>, matched| Step {
+            workflow: workflow.to_owned(),
+            rule,
+            condition: condition.map(str::to_owned),
+            matched,
+        };
+        assert_eq!(
+            trace,
+            Trace {
+                steps: vec![
+                    step("in", 0, Some("x<5&&m>=2"), false),
+                    step("in", 1, Some("x>=5||m!=1"), true),
+                    step("foo", 0, None, true),
+                ],
+                accepted: true,
+            }
+        );
+    }
+
     #[test]
     fn part1_sample_input() {
         assert_eq!(part1(SAMPLE_INPUT).unwrap(), 19114);
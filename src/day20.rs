@@ -114,7 +114,8 @@ Reset all modules to their default states. Waiting for all pulses to be fully ha
 
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
-use anyhow::anyhow;
+use crate::cycle;
+use anyhow::{anyhow, bail};
 use nom::{
     bytes::complete::tag,
     character::complete::{alpha1, multispace0, newline, one_of, space0},
@@ -137,8 +138,6 @@ pub fn part1(input: &str) -> anyhow::Result<usize> {
     Ok(lo * hi)
 }
 pub fn part2(input: &str) -> anyhow::Result<usize> {
-    let mut graph = parse_input(input)?;
-
     // This is garbage.
     // The direct approach is too slow. If we look at the input:
     //   &dn -> rx
@@ -147,19 +146,51 @@ pub fn part2(input: &str) -> anyhow::Result<usize> {
     // Maybe this is the result of some clever insight, but rather than building a real
     // solution here I'm just going to hard-code the ancestors here and multiply together
     // their cycle lengths.
-    let mut i = 0;
     let ancestors = ["dd", "fh", "xp", "fc"];
     let mut cycle_lengths = Vec::new();
-    loop {
-        let r = graph.signal("broadcaster", Signal::Lo)?;
-        i += 1;
-        if ancestors.iter().any(|&n| r.recv_lo.contains(n)) {
-            cycle_lengths.push(i);
-            if cycle_lengths.len() == ancestors.len() {
-                return Ok(cycle_lengths.into_iter().reduce(lcm).unwrap());
-            }
+    for ancestor in ancestors {
+        let mut graph = parse_input(input)?;
+        // Only the modules feeding into `ancestor` decide when it fires, so the cycle we
+        // want is the cycle of their states, even though the graph as a whole takes far
+        // longer to repeat.
+        let upstream = graph.upstream(ancestor);
+        let mut presses = 0;
+        let mut fired = Vec::new();
+        let mut error = None;
+        let cycle = cycle::hashed(
+            &mut graph,
+            |graph| {
+                // Once a press fails, stop pressing, so that the states repeat and
+                // `hashed` returns.
+                if error.is_some() {
+                    return;
+                }
+                match graph.signal("broadcaster", Signal::Lo) {
+                    Ok(r) => {
+                        presses += 1;
+                        if r.recv_lo.contains(ancestor) {
+                            fired.push(presses);
+                        }
+                    }
+                    Err(err) => error = Some(err),
+                }
+            },
+            |graph| graph.fingerprint(&upstream),
+        );
+        if let Some(err) = error {
+            return Err(err);
+        }
+        // The starting state isn't on the cycle (mu is 1 for the real input), but as long as
+        // `ancestor` fires once, at press lambda, it fires on exactly the multiples of lambda.
+        if cycle.mu >= cycle.lambda || fired != [cycle.lambda] {
+            bail!(
+                "{ancestor} should fire once per cycle, on a multiple of its length, but it \
+                 fired at {fired:?} in {cycle:?}"
+            );
         }
+        cycle_lengths.push(cycle.lambda);
     }
+    Ok(cycle_lengths.into_iter().reduce(lcm).unwrap())
 }
 
 fn gcd(m: usize, n: usize) -> usize {
@@ -206,6 +237,36 @@ impl Graph {
         Ok(())
     }
 
+    // Every module that can influence `name`, including `name` itself.
+    fn upstream(&self, name: &str) -> BTreeSet<String> {
+        let mut seen = BTreeSet::from([name.to_owned()]);
+        let mut stack = vec![name];
+        while let Some(cur) = stack.pop() {
+            for node in self.nodes.values() {
+                if node.outputs.iter().any(|o| o == cur) && seen.insert(node.name.clone()) {
+                    stack.push(&node.name);
+                }
+            }
+        }
+        seen
+    }
+
+    // The state of just the modules in `names`: which flip-flops are on, and what each
+    // conjunction last heard (`true` for a high pulse).
+    fn fingerprint(&self, names: &BTreeSet<String>) -> Vec<bool> {
+        let mut acc = Vec::new();
+        for name in names {
+            match self.nodes.get(name).map(|n| &n.kind) {
+                Some(Kind::Flipflop { on }) => acc.push(*on),
+                Some(Kind::Conjunction { latest }) => {
+                    acc.extend(latest.values().map(|&s| s == Signal::Hi))
+                }
+                Some(Kind::Broadcast) | None => {}
+            }
+        }
+        acc
+    }
+
     fn signal(&mut self, name: &str, signal: Signal) -> anyhow::Result<PressResult> {
         let mut result = PressResult::default();
         let mut q: VecDeque<(String, Signal, String)> = VecDeque::new();
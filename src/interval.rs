@@ -0,0 +1,371 @@
+use std::{cmp, ops::Range};
+
+/// A set of integers, stored as sorted, disjoint, non-adjacent half-open ranges.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IntervalSet {
+    ranges: Vec<Range<i64>>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ranges(&self) -> &[Range<i64>] {
+        &self.ranges
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+    /// The number of integers in the set.
+    pub fn size(&self) -> i64 {
+        self.ranges.iter().map(|r| r.end - r.start).sum()
+    }
+    pub fn min(&self) -> Option<i64> {
+        self.ranges.first().map(|r| r.start)
+    }
+    pub fn contains(&self, x: i64) -> bool {
+        match self.ranges.binary_search_by(|r| r.start.cmp(&x)) {
+            Ok(_) => true,
+            Err(0) => false,
+            Err(idx) => x < self.ranges[idx - 1].end,
+        }
+    }
+
+    pub fn insert(&mut self, range: Range<i64>) {
+        *self = self.union(&IntervalSet::from(range));
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        self.ranges
+            .iter()
+            .chain(other.ranges.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        let mut acc = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (&self.ranges[i], &other.ranges[j]);
+            let lo = cmp::max(a.start, b.start);
+            let hi = cmp::min(a.end, b.end);
+            if lo < hi {
+                acc.push(lo..hi);
+            }
+            // Whichever range ends first can't overlap anything else.
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { ranges: acc }
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut acc = Vec::new();
+        let mut holes = other.ranges.iter().peekable();
+        for r in &self.ranges {
+            let mut start = r.start;
+            while start < r.end {
+                match holes.peek() {
+                    Some(h) if h.end <= start => {
+                        holes.next();
+                    }
+                    Some(h) if h.start < r.end => {
+                        if start < h.start {
+                            acc.push(start..h.start);
+                        }
+                        start = cmp::max(start, h.end);
+                    }
+                    _ => {
+                        acc.push(start..r.end);
+                        break;
+                    }
+                }
+            }
+        }
+        IntervalSet { ranges: acc }
+    }
+}
+
+impl From<Range<i64>> for IntervalSet {
+    fn from(range: Range<i64>) -> Self {
+        std::iter::once(range).collect()
+    }
+}
+
+impl FromIterator<Range<i64>> for IntervalSet {
+    fn from_iter<I: IntoIterator<Item = Range<i64>>>(iter: I) -> Self {
+        let mut ranges: Vec<Range<i64>> = iter.into_iter().filter(|r| !r.is_empty()).collect();
+        ranges.sort_by_key(|r| r.start);
+        let mut merged: Vec<Range<i64>> = Vec::with_capacity(ranges.len());
+        for r in ranges {
+            match merged.last_mut() {
+                Some(prev) if r.start <= prev.end => prev.end = cmp::max(prev.end, r.end),
+                _ => merged.push(r),
+            }
+        }
+        IntervalSet { ranges: merged }
+    }
+}
+
+/// A section of a [`RangeMap`]: every `x` in `src` maps to `x + offset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    pub src: Range<i64>,
+    pub offset: i64,
+}
+
+/// A piecewise-linear map over the integers. Anything not covered by a piece maps to itself.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RangeMap {
+    // Sorted by `src.start`, non-overlapping, and never containing a no-op (`offset == 0`) piece.
+    pieces: Vec<Piece>,
+}
+
+impl RangeMap {
+    pub fn identity() -> Self {
+        Self::default()
+    }
+
+    /// Builds a map from pieces in any order. Overlapping pieces are ambiguous, so they're rejected.
+    pub fn new(pieces: impl IntoIterator<Item = Piece>) -> anyhow::Result<Self> {
+        let mut pieces: Vec<Piece> = pieces.into_iter().filter(|p| !p.src.is_empty()).collect();
+        pieces.sort_by_key(|p| p.src.start);
+        if let Some(w) = pieces.windows(2).find(|w| w[0].src.end > w[1].src.start) {
+            anyhow::bail!("overlapping pieces: {:?} and {:?}", w[0], w[1]);
+        }
+        Ok(Self::normalized(pieces))
+    }
+
+    pub fn pieces(&self) -> &[Piece] {
+        &self.pieces
+    }
+
+    pub fn apply(&self, x: i64) -> i64 {
+        let idx = self.pieces.partition_point(|p| p.src.end <= x);
+        match self.pieces.get(idx) {
+            Some(p) if p.src.start <= x => x + p.offset,
+            _ => x,
+        }
+    }
+
+    pub fn apply_set(&self, input: &IntervalSet) -> IntervalSet {
+        input
+            .ranges()
+            .iter()
+            .flat_map(|r| self.split(r.clone()))
+            .map(|p| p.src.start + p.offset..p.src.end + p.offset)
+            .collect()
+    }
+
+    /// Everything that maps into `target`. The map need not be injective, so this may be
+    /// larger than `target`.
+    pub fn preimage(&self, target: &IntervalSet) -> IntervalSet {
+        let domain: IntervalSet = self.pieces.iter().map(|p| p.src.clone()).collect();
+        let mut acc = target.difference(&domain).ranges().to_vec();
+        for p in &self.pieces {
+            let image = IntervalSet::from(p.src.start + p.offset..p.src.end + p.offset);
+            for r in image.intersection(target).ranges() {
+                acc.push(r.start - p.offset..r.end - p.offset);
+            }
+        }
+        acc.into_iter().collect()
+    }
+
+    /// The map that applies `self`, then `next`.
+    pub fn then(&self, next: &RangeMap) -> RangeMap {
+        let mut acc = Vec::new();
+        for p in &self.pieces {
+            let image = p.src.start + p.offset..p.src.end + p.offset;
+            for q in next.split(image) {
+                acc.push(Piece {
+                    src: q.src.start - p.offset..q.src.end - p.offset,
+                    offset: p.offset + q.offset,
+                });
+            }
+        }
+        // Outside of our pieces we're the identity, so `next` applies unchanged.
+        let domain: IntervalSet = self.pieces.iter().map(|p| p.src.clone()).collect();
+        for q in &next.pieces {
+            for r in IntervalSet::from(q.src.clone())
+                .difference(&domain)
+                .ranges()
+            {
+                acc.push(Piece {
+                    src: r.clone(),
+                    offset: q.offset,
+                });
+            }
+        }
+        acc.sort_by_key(|p| p.src.start);
+        Self::normalized(acc)
+    }
+
+    // Covers `input` with pieces, filling any gaps with no-op pieces.
+    fn split(&self, mut input: Range<i64>) -> Vec<Piece> {
+        let mut acc = Vec::new();
+        let idx = self.pieces.partition_point(|p| p.src.end <= input.start);
+        for p in &self.pieces[idx..] {
+            if input.is_empty() || p.src.start >= input.end {
+                break;
+            }
+            if input.start < p.src.start {
+                acc.push(Piece {
+                    src: input.start..p.src.start,
+                    offset: 0,
+                });
+                input.start = p.src.start;
+            }
+            let hi = cmp::min(input.end, p.src.end);
+            acc.push(Piece {
+                src: input.start..hi,
+                offset: p.offset,
+            });
+            input.start = hi;
+        }
+        if !input.is_empty() {
+            acc.push(Piece {
+                src: input,
+                offset: 0,
+            });
+        }
+        acc
+    }
+
+    // Expects sorted, non-overlapping pieces. Merges adjacent pieces that share an offset.
+    fn normalized(pieces: Vec<Piece>) -> Self {
+        let mut merged: Vec<Piece> = Vec::with_capacity(pieces.len());
+        for p in pieces {
+            if p.offset == 0 || p.src.is_empty() {
+                continue;
+            }
+            match merged.last_mut() {
+                Some(prev) if prev.src.end == p.src.start && prev.offset == p.offset => {
+                    prev.src.end = p.src.end
+                }
+                _ => merged.push(p),
+            }
+        }
+        RangeMap { pieces: merged }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn set(ranges: &[Range<i64>]) -> IntervalSet {
+        ranges.iter().cloned().collect()
+    }
+
+    #[test]
+    fn normalize_smoke_test() {
+        assert_eq!(set(&[5..7, 0..2, 2..3, 6..9, 4..4]).ranges(), &[0..3, 5..9]);
+    }
+
+    #[test]
+    fn set_algebra_smoke_test() {
+        let a = set(&[0..10, 20..30]);
+        let b = set(&[5..25, 28..29]);
+        assert_eq!(a.union(&b), IntervalSet::from(0..30));
+        assert_eq!(a.intersection(&b).ranges(), &[5..10, 20..25, 28..29]);
+        assert_eq!(a.difference(&b).ranges(), &[0..5, 25..28, 29..30]);
+        assert_eq!(b.difference(&a), IntervalSet::from(10..20));
+        assert_eq!(a.size(), 20);
+        assert!(a.contains(0) && a.contains(25) && !a.contains(10) && !a.contains(-1));
+    }
+
+    #[test]
+    fn range_map_smoke_test() {
+        let m = RangeMap::new([
+            Piece {
+                src: 6..9,
+                offset: 10,
+            },
+            Piece {
+                src: 1..4,
+                offset: 10,
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            (0..10).map(|x| m.apply(x)).collect::<Vec<_>>(),
+            vec![0, 11, 12, 13, 4, 5, 16, 17, 18, 9]
+        );
+        assert_eq!(
+            m.apply_set(&IntervalSet::from(0..10)).ranges(),
+            &[0..1, 4..6, 9..10, 11..14, 16..19]
+        );
+    }
+
+    #[test]
+    fn range_map_rejects_overlap() {
+        let overlapping = [
+            Piece {
+                src: 0..5,
+                offset: 1,
+            },
+            Piece {
+                src: 4..6,
+                offset: 2,
+            },
+        ];
+        assert!(RangeMap::new(overlapping).is_err());
+    }
+
+    #[test]
+    fn preimage_matches_apply() {
+        let m = RangeMap::new([
+            Piece {
+                src: 0..10,
+                offset: 5,
+            },
+            Piece {
+                src: 20..25,
+                offset: -18,
+            },
+        ])
+        .unwrap();
+        let target = set(&[3..8, 14..17]);
+        let preimage = m.preimage(&target);
+        for x in -5..40 {
+            assert_eq!(preimage.contains(x), target.contains(m.apply(x)), "x = {x}");
+        }
+    }
+
+    #[test]
+    fn composition_matches_sequential_application() {
+        let f = RangeMap::new([
+            Piece {
+                src: 0..10,
+                offset: 5,
+            },
+            Piece {
+                src: 20..25,
+                offset: -20,
+            },
+        ])
+        .unwrap();
+        let g = RangeMap::new([
+            Piece {
+                src: 3..12,
+                offset: 100,
+            },
+            Piece {
+                src: 14..16,
+                offset: -1,
+            },
+        ])
+        .unwrap();
+        let fg = f.then(&g);
+        for x in -5..40 {
+            assert_eq!(fg.apply(x), g.apply(f.apply(x)), "x = {x}");
+        }
+        let input = IntervalSet::from(-5..40);
+        assert_eq!(fg.apply_set(&input), g.apply_set(&f.apply_set(&input)));
+    }
+}
@@ -1,4 +1,8 @@
+pub mod cycle;
 pub mod grid;
+pub mod interval;
+pub mod polygon;
+pub mod sequence;
 
 pub mod day01;
 pub mod day02;
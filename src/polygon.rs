@@ -0,0 +1,321 @@
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail};
+
+use crate::grid::Direction;
+
+/// Which way a polygon's vertices go around, with x increasing to the right and y
+/// increasing upwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+    /// The polygon has no area, so it doesn't go around anything.
+    Degenerate,
+}
+
+/// A closed polygon with its vertices on the integer lattice. The last vertex connects back
+/// to the first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polygon {
+    vertices: Vec<(i64, i64)>,
+}
+
+impl Polygon {
+    pub fn new(vertices: Vec<(i64, i64)>) -> Self {
+        Polygon { vertices }
+    }
+
+    /// Traces a polygon by moving `distance` in each direction, starting from the origin.
+    /// Up is +y and right is +x. The moves have to end up back at the origin.
+    pub fn from_moves(moves: impl IntoIterator<Item = (Direction, i64)>) -> anyhow::Result<Self> {
+        let mut vertices = Vec::new();
+        let (mut x, mut y) = (0i64, 0i64);
+        for (direction, distance) in moves {
+            vertices.push((x, y));
+            let overflow = || anyhow!("moving {distance} {direction:?} from ({x}, {y}) overflows");
+            (x, y) = match direction {
+                Direction::Up => (x, y.checked_add(distance).ok_or_else(overflow)?),
+                Direction::Down => (x, y.checked_sub(distance).ok_or_else(overflow)?),
+                Direction::Left => (x.checked_sub(distance).ok_or_else(overflow)?, y),
+                Direction::Right => (x.checked_add(distance).ok_or_else(overflow)?, y),
+            };
+        }
+        if (x, y) != (0, 0) {
+            bail!("moves did not form a closed loop, started at (0, 0), ended at ({x}, {y})");
+        }
+        Ok(Polygon { vertices })
+    }
+
+    pub fn vertices(&self) -> &[(i64, i64)] {
+        &self.vertices
+    }
+
+    /// Twice the signed area, which is always an integer: positive if the polygon winds
+    /// counter-clockwise, negative if it winds clockwise.
+    pub fn twice_signed_area(&self) -> anyhow::Result<i128> {
+        // The shoelace formula: the sum of x[k] * y[k + 1] - x[k + 1] * y[k] over all edges.
+        self.edges().try_fold(0i128, |acc, ((x0, y0), (x1, y1))| {
+            // Each product fits in an i128, but their difference might not.
+            (x0 as i128 * y1 as i128)
+                .checked_sub(x1 as i128 * y0 as i128)
+                .and_then(|cross| acc.checked_add(cross))
+                .ok_or_else(|| anyhow!("polygon area overflows i128"))
+        })
+    }
+
+    pub fn winding(&self) -> anyhow::Result<Winding> {
+        Ok(match self.twice_signed_area()?.signum() {
+            1 => Winding::CounterClockwise,
+            -1 => Winding::Clockwise,
+            _ => Winding::Degenerate,
+        })
+    }
+
+    /// The number of lattice points on the edges.
+    pub fn boundary_points(&self) -> anyhow::Result<i128> {
+        // An edge from (x0, y0) to (x1, y1) passes through gcd(|dx|, |dy|) lattice points,
+        // counting one end but not the other.
+        self.edges().try_fold(0i128, |acc, ((x0, y0), (x1, y1))| {
+            let dx = (x1 as i128 - x0 as i128).unsigned_abs();
+            let dy = (y1 as i128 - y0 as i128).unsigned_abs();
+            i128::try_from(gcd(dx, dy))
+                .ok()
+                .and_then(|points| acc.checked_add(points))
+                .ok_or_else(|| anyhow!("polygon perimeter overflows i128"))
+        })
+    }
+
+    /// The number of lattice points strictly inside the polygon. Only meaningful if the
+    /// polygon is simple (doesn't cross or overlap itself).
+    pub fn interior_points(&self) -> anyhow::Result<i128> {
+        // Pick's theorem: A = I + B / 2 - 1, so 2I = 2A - B + 2.
+        let twice_area = self.twice_signed_area()?.abs();
+        let boundary = self.boundary_points()?;
+        if self.vertices.is_empty() || twice_area == 0 {
+            return Ok(0);
+        }
+        Ok((twice_area - boundary + 2) / 2)
+    }
+
+    /// Every lattice point inside or on the edge of the polygon, i.e. its area in unit
+    /// squares if each lattice point were the center of a square.
+    pub fn enclosed_points(&self) -> anyhow::Result<i128> {
+        self.interior_points()?
+            .checked_add(self.boundary_points()?)
+            .ok_or_else(|| anyhow!("enclosed point count overflows i128"))
+    }
+
+    /// Finds two edges, by index, that cross, touch or overlap each other, other than
+    /// neighbouring edges meeting at their shared vertex. Edge `k` runs from vertex `k` to
+    /// vertex `k + 1`. Zero-length edges are ignored, so the edges either side of one count
+    /// as neighbours. Checks every pair, so it's quadratic in the number of vertices.
+    pub fn self_intersection(&self) -> Option<(usize, usize)> {
+        // A repeated vertex would otherwise "meet" both of the edges around it.
+        let edges: Vec<(usize, Segment)> = self
+            .edges()
+            .enumerate()
+            .filter(|&(_, (p, q))| p != q)
+            .collect();
+        let n = edges.len();
+        for k in 0..n {
+            for l in k + 1..n {
+                let ((i, a), (j, b)) = (edges[k], edges[l]);
+                let hit = if l == k + 1 {
+                    doubles_back(a, b)
+                } else if k == 0 && l == n - 1 {
+                    doubles_back(b, a)
+                } else {
+                    segments_meet(a, b)
+                };
+                if hit {
+                    return Some((i, j));
+                }
+            }
+        }
+        None
+    }
+
+    fn edges(&self) -> impl Iterator<Item = ((i64, i64), (i64, i64))> + '_ {
+        self.vertices
+            .iter()
+            .copied()
+            .zip(self.vertices.iter().copied().cycle().skip(1))
+    }
+}
+
+type Segment = ((i64, i64), (i64, i64));
+
+// The sign of the cross product (q - p) x (r - p): which side of p -> q the point r is on.
+fn orientation(p: (i64, i64), q: (i64, i64), r: (i64, i64)) -> i128 {
+    let (dx0, dy0) = (q.0 as i128 - p.0 as i128, q.1 as i128 - p.1 as i128);
+    let (dx1, dy1) = (r.0 as i128 - p.0 as i128, r.1 as i128 - p.1 as i128);
+    compare_products((dx0, dy1), (dx1, dy0)) as i128
+}
+
+// Compares a * b with c * d. The factors are differences of i64s, so their products can be
+// just too big for an i128, but their magnitudes always fit in a u128.
+fn compare_products((a, b): (i128, i128), (c, d): (i128, i128)) -> Ordering {
+    let (s0, s1) = (a.signum() * b.signum(), c.signum() * d.signum());
+    let (m0, m1) = (
+        a.unsigned_abs() * b.unsigned_abs(),
+        c.unsigned_abs() * d.unsigned_abs(),
+    );
+    match (s0, s1) {
+        (1, 1) => m0.cmp(&m1),
+        (-1, -1) => m1.cmp(&m0),
+        _ => s0.cmp(&s1),
+    }
+}
+
+// Whether r lies within the bounding box of p -> q. Only meaningful if they're collinear.
+fn within(p: (i64, i64), q: (i64, i64), r: (i64, i64)) -> bool {
+    p.0.min(q.0) <= r.0 && r.0 <= p.0.max(q.0) && p.1.min(q.1) <= r.1 && r.1 <= p.1.max(q.1)
+}
+
+fn segments_meet((p0, p1): Segment, (q0, q1): Segment) -> bool {
+    let (o0, o1) = (orientation(p0, p1, q0), orientation(p0, p1, q1));
+    let (o2, o3) = (orientation(q0, q1, p0), orientation(q0, q1, p1));
+    if o0 * o1 < 0 && o2 * o3 < 0 {
+        return true;
+    }
+    (o0 == 0 && within(p0, p1, q0))
+        || (o1 == 0 && within(p0, p1, q1))
+        || (o2 == 0 && within(q0, q1, p0))
+        || (o3 == 0 && within(q0, q1, p1))
+}
+
+// Whether `b`, which starts where `a` ends, heads back along `a`.
+fn doubles_back((p0, p1): Segment, (_, q1): Segment) -> bool {
+    if orientation(p0, p1, q1) != 0 {
+        return false;
+    }
+    let (dx0, dy0) = (p1.0 as i128 - p0.0 as i128, p1.1 as i128 - p0.1 as i128);
+    let (dx1, dy1) = (q1.0 as i128 - p1.0 as i128, q1.1 as i128 - p1.1 as i128);
+    // They're collinear, so heading back means one of the components changes sign.
+    (dx0.signum() * dx1.signum() < 0) || (dy0.signum() * dy1.signum() < 0)
+}
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn square_smoke_test() {
+        let square = Polygon::new(vec![(0, 0), (4, 0), (4, 4), (0, 4)]);
+        assert_eq!(square.twice_signed_area().unwrap(), 32);
+        assert_eq!(square.winding().unwrap(), Winding::CounterClockwise);
+        assert_eq!(square.boundary_points().unwrap(), 16);
+        assert_eq!(square.interior_points().unwrap(), 9);
+        assert_eq!(square.enclosed_points().unwrap(), 25);
+
+        let reversed = Polygon::new(square.vertices().iter().rev().copied().collect());
+        assert_eq!(reversed.twice_signed_area().unwrap(), -32);
+        assert_eq!(reversed.winding().unwrap(), Winding::Clockwise);
+        assert_eq!(reversed.interior_points().unwrap(), 9);
+    }
+
+    #[test]
+    fn diagonal_edges() {
+        // A triangle with a half-integer area, and edges that skip lattice points.
+        let triangle = Polygon::new(vec![(0, 0), (3, 1), (1, 3)]);
+        assert_eq!(triangle.twice_signed_area().unwrap(), 8);
+        assert_eq!(triangle.boundary_points().unwrap(), 4);
+        assert_eq!(triangle.interior_points().unwrap(), 3);
+    }
+
+    #[test]
+    fn from_moves_smoke_test() {
+        let moves = [
+            (Direction::Right, 2),
+            (Direction::Down, 2),
+            (Direction::Left, 2),
+            (Direction::Up, 2),
+        ];
+        let p = Polygon::from_moves(moves).unwrap();
+        assert_eq!(p.vertices(), &[(0, 0), (2, 0), (2, -2), (0, -2)]);
+        assert_eq!(p.winding().unwrap(), Winding::Clockwise);
+        assert_eq!(p.enclosed_points().unwrap(), 9);
+
+        assert!(Polygon::from_moves([(Direction::Up, 1)]).is_err());
+        assert!(Polygon::from_moves([(Direction::Up, i64::MAX), (Direction::Up, 1)]).is_err());
+    }
+
+    #[test]
+    fn degenerate() {
+        let line = Polygon::new(vec![(0, 0), (5, 0)]);
+        assert_eq!(line.winding().unwrap(), Winding::Degenerate);
+        assert_eq!(line.boundary_points().unwrap(), 10);
+        assert_eq!(line.interior_points().unwrap(), 0);
+        assert_eq!(Polygon::new(vec![]).enclosed_points().unwrap(), 0);
+    }
+
+    #[test]
+    fn self_intersection() {
+        let square = Polygon::new(vec![(0, 0), (4, 0), (4, 4), (0, 4)]);
+        assert_eq!(square.self_intersection(), None);
+        // A figure eight.
+        let bowtie = Polygon::new(vec![(0, 0), (4, 4), (4, 0), (0, 4)]);
+        assert_eq!(bowtie.self_intersection(), Some((0, 2)));
+        // Two squares touching at a corner.
+        let touching = Polygon::new(vec![
+            (0, 0),
+            (2, 0),
+            (2, 2),
+            (4, 2),
+            (4, 4),
+            (2, 4),
+            (2, 2),
+            (0, 2),
+        ]);
+        assert_eq!(touching.self_intersection(), Some((1, 5)));
+        // Doubling back along the previous edge, including around the wrap.
+        let spur = Polygon::new(vec![(0, 0), (4, 0), (6, 0), (5, 0), (4, 4)]);
+        assert_eq!(spur.self_intersection(), Some((1, 2)));
+        let spur = Polygon::new(vec![
+            (1, 0),
+            (2, 0),
+            (2, -2),
+            (4, -2),
+            (4, 1),
+            (3, 1),
+            (3, 0),
+        ]);
+        assert_eq!(spur.self_intersection(), Some((0, 6)));
+        // Repeated vertices aren't intersections, but indices still count their edges.
+        let repeated = Polygon::new(vec![(0, 0), (2, 0), (2, 0), (2, 2), (0, 2), (0, 0)]);
+        assert_eq!(repeated.self_intersection(), None);
+        let bowtie = Polygon::new(vec![(0, 0), (0, 0), (4, 4), (4, 0), (0, 4)]);
+        assert_eq!(bowtie.self_intersection(), Some((1, 3)));
+    }
+
+    #[test]
+    fn huge_coordinates() {
+        let m = 1 << 61;
+        let corners = [(-m, -m), (m, -m), (m, m), (-m, m)];
+        let square = Polygon::new(corners.to_vec());
+        let side = 2 * m as i128;
+        assert_eq!(square.twice_signed_area().unwrap(), 2 * side * side);
+        assert_eq!(square.boundary_points().unwrap(), 4 * side);
+        // Going around four times is enough to overflow.
+        let square = Polygon::new(corners.repeat(4));
+        assert!(square.twice_signed_area().is_err());
+        let triangle = Polygon::new(vec![(i64::MIN, i64::MIN), (i64::MAX, i64::MIN), (0, 1)]);
+        assert!(triangle.twice_signed_area().is_err());
+        assert_eq!(triangle.self_intersection(), None);
+        let bowtie = Polygon::new(vec![
+            (i64::MIN, i64::MIN),
+            (i64::MAX, i64::MAX),
+            (i64::MAX, i64::MIN),
+            (i64::MIN, i64::MAX),
+        ]);
+        assert_eq!(bowtie.self_intersection(), Some((0, 2)));
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::{anyhow, bail};
+
+/// The lowest-degree polynomial through samples taken at 0, 1, 2, ..., stored in Newton
+/// form: f(x) = sum over k of C(x, k) * Δ^k f(0).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Polynomial {
+    // Δ^k f(0) for k = 0..=degree. Empty for the zero polynomial.
+    coefficients: Vec<i128>,
+}
+
+impl Polynomial {
+    /// Fits `samples` by repeatedly differencing them until nothing is left but zeros. Fails
+    /// rather than wrapping if a difference overflows.
+    pub fn fit(samples: &[i128]) -> anyhow::Result<Self> {
+        if samples.is_empty() {
+            bail!("can't fit a polynomial to no samples");
+        }
+        // Each pass replaces the table with its differences, in place.
+        let mut table = samples.to_vec();
+        let mut coefficients = Vec::new();
+        while table.iter().any(|&x| x != 0) {
+            coefficients.push(table[0]);
+            for i in 0..table.len() - 1 {
+                table[i] = table[i + 1]
+                    .checked_sub(table[i])
+                    .ok_or_else(|| anyhow!("differences of {samples:?} overflow i128"))?;
+            }
+            table.pop();
+        }
+        Ok(Polynomial { coefficients })
+    }
+
+    /// `None` for the zero polynomial. With `n` samples this is at most `n - 1`; anything less
+    /// means the samples were enough to pin the polynomial down.
+    pub fn degree(&self) -> Option<usize> {
+        self.coefficients.len().checked_sub(1)
+    }
+
+    /// The value at `x`, which can be anywhere: before the first sample, between them, or
+    /// after the last one.
+    pub fn at(&self, x: i128) -> anyhow::Result<i128> {
+        let overflow = || anyhow!("value at {x} overflows i128");
+        let mut total: i128 = 0;
+        // C(x, k), which is well-defined (and an integer) for negative x too.
+        let mut binomial: i128 = 1;
+        for (k, &c) in (0..).zip(&self.coefficients) {
+            if k > 0 {
+                // C(x, k) = C(x, k - 1) * (x - k + 1) / k, and the division is exact.
+                binomial = x
+                    .checked_sub(k - 1)
+                    .and_then(|factor| binomial.checked_mul(factor))
+                    .ok_or_else(overflow)?
+                    / k;
+            }
+            if binomial == 0 {
+                // x is one of the sample points, and every later term vanishes too.
+                break;
+            }
+            total = binomial
+                .checked_mul(c)
+                .and_then(|term| total.checked_add(term))
+                .ok_or_else(overflow)?;
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fit_smoke_test() {
+        let p = Polynomial::fit(&[1, 3, 6, 10, 15, 21]).unwrap();
+        assert_eq!(p.degree(), Some(2));
+        assert_eq!(p.at(6).unwrap(), 28);
+        assert_eq!(p.at(-1).unwrap(), 0);
+        assert_eq!(p.at(-2).unwrap(), 0);
+        assert_eq!(p.at(-3).unwrap(), 1);
+
+        assert_eq!(Polynomial::fit(&[0, 0, 0]).unwrap().degree(), None);
+        assert_eq!(Polynomial::fit(&[7]).unwrap().degree(), Some(0));
+        assert!(Polynomial::fit(&[]).is_err());
+    }
+
+    #[test]
+    fn matches_direct_evaluation() {
+        let f = |x: i128| 3 * x.pow(4) - 7 * x.pow(3) + x - 11;
+        let samples: Vec<i128> = (0..8).map(f).collect();
+        let p = Polynomial::fit(&samples).unwrap();
+        assert_eq!(p.degree(), Some(4));
+        for x in -50..50 {
+            assert_eq!(p.at(x).unwrap(), f(x), "x = {x}");
+        }
+        assert_eq!(p.at(1_000_000).unwrap(), f(1_000_000));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        assert!(Polynomial::fit(&[i128::MIN, i128::MAX]).is_err());
+        let p = Polynomial::fit(&[0, 1, 4]).unwrap();
+        assert!(p.at(i128::MAX / 2).is_err());
+        assert!(p.at(1 << 60).is_ok());
+    }
+}